@@ -1,5 +1,87 @@
+// Generates memory.x for the chip family picked with a cargo feature.
+//
+// cortex-m-rt's link.x includes memory.x from the linker search path. It is
+// written to OUT_DIR, which is added to the path, so the flash and RAM sizes
+// always match the chip the HAL is built for.
+//
+// The F407 also has 64K of core-coupled memory (CCM) at 0x10000000. Only the
+// core can reach it, not DMA, and it is not zeroed or initialised at start-up;
+// statics go there with `#[link_section = ".ccmram"]`. The F446 has no CCM.
 
+// Imports
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Memory of one chip family, in KiB.
+struct Chip {
+    feature: &'static str,
+    flash: u32,
+    ram: u32,
+    ccm: u32,
+}
+
+const CHIPS: [Chip; 4] = [
+    Chip { feature: "stm32f401", flash: 512, ram: 96, ccm: 0 },
+    Chip { feature: "stm32f411", flash: 512, ram: 128, ccm: 0 },
+    Chip { feature: "stm32f446", flash: 512, ram: 128, ccm: 0 },
+    Chip { feature: "stm32f407", flash: 1024, ram: 128, ccm: 64 },
+];
 
 fn main() {
-    
+    let selected: Vec<&Chip> = CHIPS
+        .iter()
+        .filter(|chip| env::var_os(format!("CARGO_FEATURE_{}", chip.feature.to_uppercase())).is_some())
+        .collect();
+    let names: Vec<&str> = CHIPS.iter().map(|chip| chip.feature).collect();
+    let chip = match selected.as_slice() {
+        [chip] => chip,
+        [] => panic!("select a chip feature: {}", names.join(", ")),
+        _ => panic!("select only one chip feature, with --no-default-features for other chips than the stm32f401"),
+    };
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(out.join("memory.x"), memory_x(chip)).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+fn memory_x(chip: &Chip) -> String {
+    let mut regions = format!(
+        "  FLASH (rx) : ORIGIN = 0x08000000, LENGTH = {}K\n  RAM (rwx) : ORIGIN = 0x20000000, LENGTH = {}K\n",
+        chip.flash, chip.ram
+    );
+    let mut sections = String::new();
+    if chip.ccm > 0 {
+        regions += &format!("  CCMRAM (rwx) : ORIGIN = 0x10000000, LENGTH = {}K\n", chip.ccm);
+        sections = "
+/* Core-coupled memory, not initialised at start-up */
+SECTIONS
+{
+  .ccmram (NOLOAD) : ALIGN(4)
+  {
+    *(.ccmram .ccmram.*);
+    . = ALIGN(4);
+  } > CCMRAM
+} INSERT AFTER .bss;
+"
+        .to_string();
+    }
+
+    format!(
+        "/* Generated by build.rs for the {} */
+MEMORY
+{{
+  /* NOTE K = KiBi = 1024 bytes */
+{}}}
+{}
+/* This is where the call stack will be allocated. */
+/* The stack is of the full descending type. */
+/* NOTE Do NOT modify `_stack_start` unless you know what you are doing */
+_stack_start = ORIGIN(RAM) + LENGTH(RAM);
+",
+        chip.feature.to_uppercase(),
+        regions,
+        sections
+    )
 }
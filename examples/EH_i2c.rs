@@ -16,7 +16,7 @@ use stm32f4xx_hal::{
 };
 
 // This library
-use library::As5600; // Import your library
+use library::{As5600, Timebase}; // Import your library
 
 
 #[allow(non_snake_case)]
@@ -24,12 +24,14 @@ use library::As5600; // Import your library
 #[entry]
 fn main() -> ! {
     // ========================== Set-up ==========================
-    // Take ownership of device peripherals and split out GPIO group A and B
+    // Take ownership of core and device peripherals and split out GPIO group A and B
     let dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
 
     // Configure clocks
     let rcc = dp.RCC.constrain();
     let clocks = rcc.cfgr.freeze();
+    let timebase = Timebase::new(cp.DCB, cp.DWT, &clocks);
 
     // ========================= I2C Setup ==========================
     let gpiob = dp.GPIOB.split();
@@ -40,17 +42,14 @@ fn main() -> ! {
     let i2c = I2c::new(dp.I2C1, (scl, sda), 100.kHz(), &clocks);
     let mut encoder = As5600::new(i2c);
 
-    // Variables
-    let ms: u32 = 8_000; // clock cycles to millisecond conversion.
-
 
     // ========================== Main Loop ==========================
     loop {
         if let Ok(angle) = encoder.read_degrees() {
-            defmt::info!("Rotor position = {}°", angle);
+            info!("Rotor position = {}", angle);
         } 
 
-        // Wait 500ms
-        cortex_m::asm::delay(200 * ms);
+        // Wait 200ms
+        timebase.delay_ms(200);
     }
 }
\ No newline at end of file
@@ -0,0 +1,76 @@
+// ========================== Embedded Rust Set-up ==========================
+#![deny(unsafe_code)]
+#![no_main]
+#![no_std]
+
+
+// Imports
+use defmt::*;
+use defmt_rtt as _; // Global logger
+use panic_probe as _; // Panic handler with defmt support
+use cortex_m_rt::entry;
+use stm32f4xx_hal::{
+    prelude::*,
+    timer::Timer,
+};
+
+// This library
+use library::{As5600, Board, ClockProfile};
+use library::as5600::{As5600Analog, As5600Pwm};
+use library::as5600::config::{OutputStage, PwmFrequency};
+use library::as5600::output::AnalogRange;
+
+
+#[allow(non_snake_case)]
+#[allow(clippy::empty_loop)]
+#[entry]
+fn main() -> ! {
+    // ========================== Set-up ==========================
+    // Take ownership of the board and configure clocks
+    let board = Board::take(ClockProfile::Hsi16).unwrap();
+    let timebase = board.timebase;
+
+    // ========================= I2C Setup ==========================
+    // I2C is only needed to select the output stage. With OUTS burned to OTP it can be left out.
+    let mut encoder = As5600::new(board.i2c);
+
+    // OUT is wired to both A0 (PA0, ADC1) and D12 (PA6, TIM3 CH1).
+    let out_analog = board.a0;
+    let out_pwm = board.header.d12;
+
+
+    // ========================== Analog Output ==========================
+    if encoder.modify_config(|c| c.output_stage(OutputStage::AnalogFull)).is_err() {
+        warn!("AS5600 configuration failed");
+    }
+    let mut analog = As5600Analog::new(board.adc, out_analog, AnalogRange::Full);
+
+    for _ in 0..25 {
+        info!("Analog: rotor position = {}", analog.read_degrees());
+        timebase.delay_ms(200);
+    }
+
+
+    // ========================== PWM Output ==========================
+    // The slowest PWM frequency, so a full frame fits the 16-bit TIM3 counter.
+    if encoder
+        .modify_config(|c| c.output_stage(OutputStage::DigitalPwm).pwm_frequency(PwmFrequency::Hz115))
+        .is_err()
+    {
+        warn!("AS5600 configuration failed");
+    }
+    let capture = Timer::new(board.TIM3, &board.clocks).pwm_input(115.Hz(), out_pwm);
+    let mut pwm = As5600Pwm::new(capture);
+
+
+    // ========================== Main Loop ==========================
+    loop {
+        match pwm.read_degrees() {
+            Ok(angle) => info!("PWM: rotor position = {}", angle),
+            Err(e) => warn!("AS5600 PWM decode failed: {}", e),
+        }
+
+        // Wait 200ms
+        timebase.delay_ms(200);
+    }
+}
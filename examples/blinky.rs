@@ -13,35 +13,40 @@ use stm32f4xx_hal::{
     prelude::*,
 };
 
+// This library
+use library::Timebase;
+
 
 #[allow(non_snake_case)]
 #[allow(clippy::empty_loop)]
 #[entry]
 fn main() -> ! {
     // ========================== Set-up ==========================
-    // Take ownership of device peripherals and split out GPIO group A and B
+    // Take ownership of core and device peripherals and split out GPIO group A
     let dp = pac::Peripherals::take().unwrap();
+    let cp = cortex_m::Peripherals::take().unwrap();
     let gpioa = dp.GPIOA.split();
 
+    // Configure clocks, and time delays from them
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.freeze();
+    let timebase = Timebase::new(cp.DCB, cp.DWT, &clocks);
+
     // Configure pins
     let mut LD1 = gpioa.pa5.into_push_pull_output();
     LD1.set_low();
 
-    // Calculate conversion factor from clock cycles to ms, assuming 8 MHz
-    let ms: u32 = 8_000;
-    
-   
-  
+
    // ========================== LOOP ==========================
     loop {
         // Wait 500ms
-        cortex_m::asm::delay(500 * ms);
+        timebase.delay_ms(500);
 
         // Turn on LED LD1
         LD1.set_high();
 
         // Wait 500ms
-        cortex_m::asm::delay(500 * ms);
+        timebase.delay_ms(500);
 
         // Turn off LED LD1
         LD1.set_low();
@@ -9,11 +9,9 @@ use defmt::*;
 use defmt_rtt as _; // Global logger
 use panic_probe as _; // Panic handler with defmt support
 use cortex_m_rt::entry;
-use stm32f4xx_hal::{
-    pac::{self},
-    prelude::*,
-    i2c::I2c,
-};
+
+// This library
+use library::{As5600, Board, ClockProfile, RawAngle};
 
 
 #[allow(non_snake_case)]
@@ -21,46 +19,33 @@ use stm32f4xx_hal::{
 #[entry]
 fn main() -> ! {
     // ========================== Set-up ==========================
-    // Take ownership of device peripherals and split out GPIO group A and B
-    let dp = pac::Peripherals::take().unwrap();
-    let gpiob = dp.GPIOB.split();
-
-    // Configure clocks
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.freeze();
+    // Take ownership of the board and configure clocks
+    let board = Board::take(ClockProfile::Hsi16).unwrap();
+    let timebase = board.timebase;
 
    // ========================== Constants ==========================
-    const AS5600_ADDR: u8 = 0x36;       // 7-bit I2C address
-    const AS5600_RAW_ANGLE_REG: u8 = 0x0C; // MSB of raw angle
-    let ms: u32 = 8_000; // clock cycles to millisecond conversion.
-    let raw2deg: f32 = 360.0 / 4096.0;
-    let mut ang_rotor_deg: f32 = 0.0;
-    let mut ang_rotor_raw: u16 = 0;
+    let mut ang_rotor_raw = RawAngle::ZERO;
 
     // ========================= I2C Setup ==========================
-    let scl = gpiob.pb8.into_alternate().set_open_drain();
-    let sda = gpiob.pb9.into_alternate().set_open_drain();
-    let mut i2c = I2c::new(dp.I2C1, (scl, sda), 100.kHz(), &clocks);
+    // Arduino header I2C: SCL on PB8 (D15), SDA on PB9 (D14), 100 kHz.
+    let mut encoder = As5600::new(board.i2c).with_status_check(true); // Report magnet problems on every read
 
 
     // ========================== Main Loop ==========================
     loop {
         // Read Motor Position
-        let mut buf = [0u8; 2];
-        if i2c.write_read(AS5600_ADDR, &[AS5600_RAW_ANGLE_REG], &mut buf).is_ok() {
-            ang_rotor_raw = ((buf[0] as u16) << 8) | (buf[1] as u16);
-        }
-        else {
-            warn!("I2C read failed");
+        match encoder.read_raw_angle() {
+            Ok(raw) => ang_rotor_raw = raw,
+            Err(e) => warn!("AS5600 read failed: {}", e),
         }
 
         // Convert to degrees
-        ang_rotor_deg = (ang_rotor_raw as f32) * raw2deg;
+        let ang_rotor_deg = ang_rotor_raw.to_degrees();
         
         // Send Position over defmt
         info!("Rotor position = {}", ang_rotor_deg);
 
-        // Wait 500ms
-        cortex_m::asm::delay(200 * ms);
+        // Wait 200ms
+        timebase.delay_ms(200);
     }
 }
\ No newline at end of file
@@ -0,0 +1,45 @@
+// ========================== Embedded Rust Set-up ==========================
+#![deny(unsafe_code)]
+#![no_main]
+#![no_std]
+
+
+// Imports
+use defmt::*;
+use defmt_rtt as _; // Global logger
+use panic_probe as _; // Panic handler with defmt support
+use cortex_m_rt::entry;
+
+// This library
+use library::{As5600, Board, ClockProfile, Tca9548a};
+
+
+#[allow(non_snake_case)]
+#[allow(clippy::empty_loop)]
+#[entry]
+fn main() -> ! {
+    // ========================== Set-up ==========================
+    // Take ownership of the board and configure clocks
+    let board = Board::take(ClockProfile::Hsi16).unwrap();
+    let timebase = board.timebase;
+
+    // ========================= I2C Setup ==========================
+    // The TCA9548A hangs off the Arduino header I2C: SCL on PB8 (D15), SDA on PB9 (D14).
+    // Both AS5600s use address 0x36, so each sits on its own TCA9548A channel.
+    let mux = Tca9548a::new(board.i2c);
+    let mut pitch = As5600::new(mux.channel(0)); // Pitch axis encoder on SD0/SC0
+    let mut yaw = As5600::new(mux.channel(1));   // Yaw axis encoder on SD1/SC1
+
+
+    // ========================== Main Loop ==========================
+    loop {
+        // Read both axes, the mux switches channel automatically.
+        match (pitch.read_degrees(), yaw.read_degrees()) {
+            (Ok(p), Ok(y)) => info!("Pitch = {}, Yaw = {}", p, y),
+            (Err(e), _) | (_, Err(e)) => warn!("AS5600 read failed: {}", e),
+        }
+
+        // Wait 200ms
+        timebase.delay_ms(200);
+    }
+}
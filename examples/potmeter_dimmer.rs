@@ -8,54 +8,58 @@
 use defmt::*;
 use defmt_rtt as _; // Global logger
 use panic_probe as _; // Panic handler with defmt support
-use cortex_m::asm;
 use cortex_m_rt::entry;
 use stm32f4xx_hal::{
-    pac::{self},
-    adc::{config::AdcConfig, config::SampleTime, Adc},
+    adc::config::SampleTime,
     prelude::*,
 };
 
+// This library
+use library::dimming::Dimmer;
+use library::{Board, ClockProfile};
+use library::filters::{MedianFilter, MovingAverage, Q16};
+
 
 #[allow(non_snake_case)]
 #[allow(clippy::empty_loop)]
 #[entry]
 fn main() -> ! {
     // ========================== Set-up ==========================
-    // Take ownership of device peripherals and split out GPIO group A and B
-    let dp = pac::Peripherals::take().unwrap();
-    let gpioa = dp.GPIOA.split();
-
-    // Configure clocks
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.use_hse(8.MHz()).freeze();
+    // Take ownership of the board, clocked from the ST-LINK's 8 MHz clock
+    let board = Board::take(ClockProfile::HseMax).unwrap();
+    let timebase = board.timebase;
 
-    // Configure ADC
-    let dimmer = gpioa.pa0.into_analog();
-    let mut adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+    // Potentiometer on A0 (PA0), read with ADC1
+    let dimmer = board.a0;
+    let mut adc = board.adc;
 
-    // Configure PWM
-    let (_, (LD1_pwm, ..)) = dp.TIM2.pwm_hz(2000.Hz(), &clocks);
-    let mut LD1_pwm = LD1_pwm.with(gpioa.pa5);
+    // Configure PWM, LD2 on PA5 is TIM2 channel 1
+    let (_, (LD1_pwm, ..)) = board.TIM2.pwm_hz(2000.Hz(), &board.clocks);
+    let mut LD1_pwm = LD1_pwm.with(board.ld2);
     LD1_pwm.enable();
 
-    // Calculate conversion factor from clock cycles to ms, assuming 8 MHz
-    let ms: u32 = 8_000;
+    // Map the 12-bit ADC range onto the timer's duty range along the CIE 1931 curve.
+    let mut LD1 = Dimmer::new(LD1_pwm).unwrap();
+
+    // Configure filters: median of 3 drops single-sample spikes, averaging 8 smooths the noise.
+    let mut spikes = MedianFilter::<u16, 3>::new();
+    let mut average = MovingAverage::<Q16, 8>::new();
 
 
    // ========================== LOOP ==========================
     loop {
-        // Read Dimmer 
-        let duty = adc.convert(&dimmer, SampleTime::Cycles_480);
+        // Read and filter Dimmer
+        let sample = adc.convert(&dimmer, SampleTime::Cycles_480);
+        let sample = spikes.update(sample);
+        let brightness = average.update(Q16::from_int(sample as i32)).to_int() as u16;
 
-        // Set LED duty
-        LD1_pwm.set_duty(duty);  // Turn OFF LED
+        // Set LED brightness, 0 - 4095
+        LD1.set_brightness_12bit(brightness).unwrap();
 
-        // Print Duty
-        info!("Dimmer = {}", duty);
+        // Print brightness
+        info!("Dimmer = {}", brightness);
 
         // Delay until next cycle
-        asm::delay(100 * ms); 
-
+        timebase.delay_ms(100);
     }
 }
\ No newline at end of file
@@ -7,11 +7,14 @@
 // Imports
 use defmt_rtt as _; // Global logger
 use panic_probe as _; // Panic handler with defmt support
+use core::time::Duration;
 use cortex_m_rt::entry;
-use stm32f4xx_hal::{
-    pac::{self},
-    prelude::*,
-};
+use stm32f4xx_hal::prelude::*;
+
+// This library
+use library::button::Event;
+use library::dimming::Dimmer;
+use library::{Board, ClockProfile};
 
 
 #[allow(non_snake_case)]
@@ -19,45 +22,52 @@ use stm32f4xx_hal::{
 #[entry]
 fn main() -> ! {
     // ========================== Set-up ==========================
-    // Take ownership of device peripherals and split out GPIO group A and B
-    let dp = pac::Peripherals::take().unwrap();
-    let gpioa = dp.GPIOA.split();
-    let gpioc = dp.GPIOC.split();
-
-    // Configure clocks
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.use_hse(8.MHz()).freeze();
-
-    // Configure pins
-    let B1 = gpioc.pc13;
-
-    // Configure PWM
-    let (_, (LD1_pwm, ..)) = dp.TIM2.pwm_hz(2000.Hz(), &clocks);
-    let mut LD1_pwm = LD1_pwm.with(gpioa.pa5);
-    let max_duty = LD1_pwm.get_max_duty();
+    // Take ownership of the board, clocked from the ST-LINK's 8 MHz clock
+    let board = Board::take(ClockProfile::HseMax).unwrap();
+    let timebase = board.timebase;
+
+    // Button B1, debounced, pulls PC13 low when pressed
+    let mut B1 = board.b1;
+
+    // Configure PWM, LD2 on PA5 is TIM2 channel 1
+    let (_, (LD1_pwm, ..)) = board.TIM2.pwm_hz(2000.Hz(), &board.clocks);
+    let mut LD1_pwm = LD1_pwm.with(board.ld2);
     LD1_pwm.enable();
 
+    // Dim along the CIE 1931 curve, so every step looks equally brighter.
+    // Refreshing every 1 ms dithers the dimmest steps smooth.
+    let mut LD1 = Dimmer::new(LD1_pwm).unwrap().with_dithering();
+
     // Initialize counter variable
     let mut counter = 0;
 
-    // Calculate conversion factor from clock cycles to ms, assuming 8 MHz
-    let ms: u32 = 8_000;
-    
+    // Sample the button every 5 ms, refresh the LED every 1 ms
+    let mut button_tick = timebase.periodic(Duration::from_millis(5));
+    let mut led_tick = timebase.periodic(Duration::from_millis(1));
+
 
    // ========================== LOOP ==========================
-   loop {
-    if B1.is_low() {
-        cortex_m::asm::delay(50 * ms); // debounce delay
-        if B1.is_low() {
-            counter = (counter + 1) % 11; // increment and roll over after 10 (0-10 total 11 states)
-
-            // Optional: wait until button released
-            while B1.is_high() {}
+    loop {
+        let now = timebase.now();
+
+        if button_tick.poll(now) {
+            // Click steps up and rolls over after 10 (0-10 total 11 states), double click
+            // jumps to full brightness and a long press turns the LED off.
+            for event in B1.update(now.as_millis() as u32).unwrap() {
+                match event {
+                    Event::Click => counter = (counter + 1) % 11,
+                    Event::DoubleClick => counter = 10,
+                    Event::LongPress(_) => counter = 0,
+                    _ => {}
+                }
+            }
+
+            // Set brightness for LD1
+            LD1.set_brightness(counter as f32 / 10.0).unwrap(); // 10 steps (0%, 10%, ..., 100%)
         }
-    }
 
-    // Set PWM for LD1
-    let duty = (max_duty * counter) / 10; // 10 steps (0%, 10%, ..., 100%)
-    LD1_pwm.set_duty(duty);
+        if led_tick.poll(now) {
+            LD1.refresh().unwrap();
+        }
+    }
 }
-}
\ No newline at end of file
@@ -18,7 +18,6 @@ use rtic_monotonics::systick::prelude::*;
 
 // STM32F4 HAL
 use stm32f4xx_hal::{
-    pac::{self},
     prelude::*,
     gpio::{Output, PushPull, PA5},
 };
@@ -55,8 +54,12 @@ mod app {
         // Assign context device peripherals to dp.
         let dp = cx.device;
 
-        // Initialize the systick interrupt & obtain the token to prove that we did
-        Mono::start(cx.core.SYST, 8_000_000); // default STM32F401 clock-rate is 8MHz
+        // Configure clocks, the default is the 16 MHz internal oscillator (HSI).
+        let rcc = dp.RCC.constrain();
+        let clocks = rcc.cfgr.freeze();
+
+        // Initialize the systick interrupt at the core clock rate.
+        Mono::start(cx.core.SYST, clocks.hclk().raw());
 
         // Report that the program successfully started.
         rtt_init_print!();
@@ -8,115 +8,159 @@
 use defmt::*;
 use defmt_rtt as _; // Global logger
 use panic_probe as _; // Panic handler with defmt support
+use core::time::Duration;
 use cortex_m_rt::entry;
 use stm32f4xx_hal::{
-    pac::{self},
     prelude::*,
-    adc::{config::AdcConfig, config::SampleTime, Adc},
-    i2c::I2c,
+    adc::config::SampleTime,
 };
 
+// This library
+use library::{As5600, Board, ClockProfile, Degrees, MultiTurn, RotaryEncoder};
+use library::pid::{AntiWindup, Gains, Pid};
+use library::filters::{Ema, MedianFilter};
+use library::hbridge::{Decay, HBridge};
+use library::multi_turn::MultiTurnError;
+use library::as5600::config::{FastFilterThreshold, Hysteresis};
+use library::as5600::Direction;
+
 
 #[allow(non_snake_case)]
 #[allow(clippy::empty_loop)]
 #[entry]
 fn main() -> ! {
     // ========================== Set-up ==========================
-    // Take ownership of device peripherals and split out GPIO group A and B
-    let dp = pac::Peripherals::take().unwrap();
-    let gpioa = dp.GPIOA.split();
-    let gpiob = dp.GPIOB.split();
-
-    // Configure clocks
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.freeze();
+    // Take ownership of the board and configure clocks
+    let board = Board::take(ClockProfile::Hsi16).unwrap();
+    let timebase = board.timebase;
+    let pins = board.header;
 
    // ========================== Constants ==========================
-    const AS5600_ADDR: u8 = 0x36;       // 7-bit I2C address
-    const AS5600_RAW_ANGLE_REG: u8 = 0x0C; // MSB of raw angle
-    let ms: u32 = 8_000; // clock cycles to millisecond conversion.
-    let mut err: f32 = 0.0; // Error value
-    let mut duty: u16 = 0; // Error value
-    let mut ang_rotor: u16 = 0;
-    let mut integral: f32 = 0.0;
-    let dt = 0.1; // 100 ms loop
-    let mut set: f32 = 0.0;
-    let mut set_point_raw: f32 = 0.0;
-    let mut set_point_filtered: f32 = 0.0;
-    let mut rotor_ang_raw: f32 = 0.0;
-    let mut rotor_ang_filtered: f32 = 0.0;
-    let mut a1: f32 = 0.09;
-    let mut a2: f32 = 0.09;
-
-
-    // PID
-    const P: f32 = 10.0; // PID P-value.
-    const I: f32 = 0.0; // PID P-value.
+    let period = Duration::from_millis(100); // Control loop period
+    let dt = period.as_secs_f32();
+    let filter_cutoff = 0.15; // Set point and rotor position filter cutoff, in Hz.
+    const DEADZONE: f32 = 5.0; // Duties below this do not move the rotor.
+    const POT_RANGE: Degrees = Degrees(360.0); // Rotor travel over the full potentiometer range.
+    const ADC_MAX: f32 = 4095.0; // 12-bit ADC
+
+    // PID, on the angle error in degrees
+    const P: f32 = 114.0; // PID P-value, duty counts per degree.
+    const I: f32 = 0.0; // PID I-value.
+    const D: f32 = 5.7; // PID D-value, on rotor velocity in degrees/s.
+    const D_FILTER: f32 = 0.2; // 200 ms velocity filter
+
+    // Rotor direction that counts as positive, flip this instead of the motor wires.
+    const ROTOR_DIRECTION: Direction = Direction::Clockwise;
 
     // ========================= I2C Setup ==========================
-    let scl = gpiob.pb8.into_alternate().set_open_drain();
-    let sda = gpiob.pb9.into_alternate().set_open_drain();
-    let mut i2c = I2c::new(dp.I2C1, (scl, sda), 100.kHz(), &clocks);
+    let dir = pins.d2.into_push_pull_output(); // AS5600 DIR on D2
+    let mut encoder = As5600::new(board.i2c).with_dir_pin(dir);
+    encoder.set_direction(ROTOR_DIRECTION);
+
+    // The rotor position at power-up is zero, wherever the magnet sits.
+    if encoder.set_zero_here().is_err() {
+        warn!("AS5600 zeroing failed");
+    }
+
+    // Turn off hysteresis and the fast filter, so the loop sees every step of the rotor.
+    if encoder
+        .modify_config(|c| c.hysteresis(Hysteresis::Off).fast_filter_threshold(FastFilterThreshold::SlowOnly))
+        .is_err()
+    {
+        warn!("AS5600 configuration failed");
+    }
+
+    // Track the rotor over several turns, so crossing 4095 -> 0 is not seen as a jump.
+    let mut encoder = MultiTurn::new(encoder, 1024);
     
     // ========================= ADC Setup ==========================
-    let potmeter = gpioa.pa0.into_analog();
-    let mut adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+    let potmeter = board.a0;
+    let mut adc = board.adc;
 
     // =================== DC Motor Driver Setup ====================
-    let (_, (IN1_pwm, IN2_pwm, ..)) = dp.TIM1.pwm_hz(2000.Hz(), &clocks);
-    let mut IN1_pwm = IN1_pwm.with(gpioa.pa8);
-    let mut IN2_pwm = IN2_pwm.with(gpioa.pa9);
-    IN1_pwm.disable();
-    IN2_pwm.disable();
-
+    let (_, (IN1_pwm, IN2_pwm, ..)) = board.TIM1.pwm_hz(2000.Hz(), &board.clocks);
+    let mut IN1_pwm = IN1_pwm.with(pins.d7); // IN1 on D7 (PA8)
+    let mut IN2_pwm = IN2_pwm.with(pins.d8); // IN2 on D8 (PA9)
+    let max_duty = IN1_pwm.get_max_duty() as f32;
+    IN1_pwm.enable();
+    IN2_pwm.enable();
+
+    // Coast in the deadzone, and let the motor settle for 1 ms before reversing.
+    let mut motor = HBridge::new(IN1_pwm, IN2_pwm, &timebase)
+        .unwrap()
+        .with_decay(Decay::Coast)
+        .with_deadband(DEADZONE / max_duty)
+        .with_dead_time_us(1_000);
+
+    // ========================== Controller ==========================
+    // Output in duty counts, signed by direction, so the integrator stops at full duty.
+    let mut pid = Pid::new(Gains { kp: P, ki: I, kd: D })
+        .with_output_limits(-max_duty, max_duty)
+        .with_derivative_filter(D_FILTER)
+        .with_anti_windup(AntiWindup::Clamping);
+
+    // ========================== Filters ==========================
+    // Median of 5 drops ADC spikes from the motor, then both signals are low-passed.
+    let mut set_point_spikes = MedianFilter::<u16, 5>::new();
+    let mut set_point_filter = Ema::<f32>::from_cutoff(filter_cutoff, 1.0 / dt);
+    let mut rotor_filter = Ema::<f32>::from_cutoff(filter_cutoff, 1.0 / dt);
+
+    let mut ang_rotor = Degrees(0.0);
+    let mut control_tick = timebase.periodic(period);
 
     // ========================== Main Loop ==========================
     loop {
-        // Read Motor Position
-        let mut buf = [0u8; 2];
-        if i2c.write_read(AS5600_ADDR, &[AS5600_RAW_ANGLE_REG], &mut buf).is_ok() {
-            ang_rotor = ((buf[0] as u16) << 8) | (buf[1] as u16);
-        }
-        else {
-            warn!("I2C read failed");
+        // Run the controller once per period
+        if !control_tick.poll(timebase.now()) {
+            continue;
         }
 
-        // Read Potentiometer position
-        let set_point: u16 = adc.convert(&potmeter, SampleTime::Cycles_480);
+        // Read Motor Position
+        ang_rotor = read_rotor(&mut encoder, ang_rotor);
 
+        // Read Potentiometer position, and scale it to a rotor angle
+        let set_point: u16 = adc.convert(&potmeter, SampleTime::Cycles_480);
+        let set_point = set_point_spikes.update(set_point);
+        let set_point = Degrees(set_point as f32 / ADC_MAX * POT_RANGE.0);
 
         // Filter position
-        set_point_filtered = a1 * (set_point as f32) + (1.0 - a1) * set_point_filtered;
-        rotor_ang_filtered = a2 * (ang_rotor as f32) + (1.0 - a2) * rotor_ang_filtered;
-
-
-        // Calculate error and add P-factor
-        err = set_point_filtered - rotor_ang_filtered;
-        integral += err * dt;
-        set = err*P + integral*I;
-
-        // Apply duty cycle to motor driver
-        duty = set.abs() as u16; // Calculate absolute value and convert to unsigned int.
-        if duty < 5 { // Deadzone
-            duty = 0;
-        } else {
-            IN1_pwm.set_duty(duty);  
-            IN2_pwm.set_duty(duty);  
-        }
+        let set_point_filtered = Degrees(set_point_filter.update(set_point.0));
+        let rotor_ang_filtered = Degrees(rotor_filter.update(ang_rotor.0));
 
-        // Set motor direction
-        if set >= 0.0 { // Forward -->
-            IN2_pwm.disable();
-            IN1_pwm.enable();
 
-        } else {        // Backwards <--
-            IN1_pwm.disable();
-            IN2_pwm.enable();  
-        }
-        
-        cortex_m::asm::delay(100 * ms);
+        // PID on the rotor angle, damped by the rotor velocity
+        let set = pid.update(set_point_filtered.0, rotor_ang_filtered.0, dt);
+
+        // Apply duty cycle to motor driver, the sign sets the direction.
+        motor.set_output(set / max_duty).unwrap();
+
         info!("Pot = {}", set_point_filtered);
         info!("Rotor = {}", rotor_ang_filtered);
-        info!("Error = {}", set);
+        info!("Output = {}", set);
+    }
+}
+
+
+// Functions
+// Reads the unwrapped rotor angle, keeping `last` on a failed read.
+// Written against RotaryEncoder, so any encoder backend can drive the servo.
+fn read_rotor<ENC>(rotor: &mut MultiTurn<ENC>, last: Degrees) -> Degrees
+where
+    ENC: RotaryEncoder,
+    ENC::Error: Format,
+{
+    match rotor.update() {
+        Ok(_) => rotor.degrees(),
+        Err(MultiTurnError::Sensor(e)) => {
+            warn!("Encoder read failed: {}", e);
+            last
+        }
+        Err(MultiTurnError::AmbiguousStep(_)) => {
+            warn!("Rotor moved too far between samples, resyncing");
+            match rotor.resync() {
+                Ok(_) => rotor.degrees(),
+                Err(_) => last,
+            }
+        }
     }
-}
\ No newline at end of file
+}
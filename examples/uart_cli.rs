@@ -11,35 +11,20 @@ use panic_probe as _;
 // UART Specific
 use core::fmt::Write; // Used for formatted text over UART. 
 use heapless::String; // fixed-capacity string
-use stm32f4xx_hal::{
-    pac,
-    prelude::*,
-    serial::{
-        config::Config, // Struct for storing the UART configuration.
-        Serial // Struct used to initialize UART and pin configuration. 
-    },
-};
+use stm32f4xx_hal::prelude::*; // Serial read trait.
+
+// This library
+use library::{Board, ClockProfile}; // Board pins and serial port.
 
 
 
 #[entry]
 fn main() -> ! {
-    // Take ownership of peripherals and configure clocks
-    let dp = pac::Peripherals::take().unwrap();
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.freeze();
-
-    // Split out GPIO group A and configure rx and tx pins.
-    let gpioa = dp.GPIOA.split();
-    let tx = gpioa.pa2.into_alternate();
-    let rx = gpioa.pa3.into_alternate();
-
-    // Configure UART communication for 115200 baud rate on USART2. 
-    let serial_config = Config::default().baudrate(115_200.bps());
-    let mut serial = Serial::new(dp.USART2, (tx, rx), serial_config, &clocks).unwrap();
-
-    // Split serial object into receiver and transmitter.
-    let (mut tx, mut rx) = serial.split();
+    // Take ownership of the board and configure clocks
+    let board = Board::take(ClockProfile::Hsi16).unwrap();
+
+    // Split the ST-LINK virtual COM port (USART2, 115200 baud) into receiver and transmitter.
+    let (mut tx, mut rx) = board.vcp.split();
 
     // Message buffer
     let mut buffer: String<64> = String::new();
@@ -0,0 +1,131 @@
+// Compiler directives
+#![no_std]
+#![no_main]
+
+// Libraries
+// Generic
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use critical_section::Mutex;
+use defmt_rtt as _;
+use panic_probe as _;
+
+// UART Specific
+use core::fmt::Write; // Used for formatting frames.
+use heapless::String; // fixed-capacity string
+use stm32f4xx_hal::{
+    dma::StreamsTuple,
+    pac::{interrupt, Interrupt},
+    prelude::*,
+    serial::config::Config,
+};
+
+// This library
+use library::dma_serial::{Usart2DmaRx, Usart2DmaTx};
+use library::{Board, ClockProfile, DmaBuffers}; // Board pins, serial port and DMA buffers.
+
+
+
+// Serial halves, shared with the interrupt handlers.
+static TX: Mutex<RefCell<Option<Usart2DmaTx<64>>>> = Mutex::new(RefCell::new(None));
+static RX: Mutex<RefCell<Option<Usart2DmaRx<64>>>> = Mutex::new(RefCell::new(None));
+
+// Telemetry period in ms, set with "rate <ms>".
+static RATE_MS: AtomicU32 = AtomicU32::new(100);
+
+
+#[entry]
+fn main() -> ! {
+    // Take ownership of the board and configure clocks
+    let board = Board::take(ClockProfile::HsiMax).unwrap();
+    let timebase = board.timebase;
+
+    // USART2 TX on DMA1 stream 6, RX on DMA1 stream 5.
+    let dma1 = StreamsTuple::new(board.DMA1);
+    let buffers = cortex_m::singleton!(: DmaBuffers<64, 64> = DmaBuffers::new()).unwrap();
+    let config = Config::default().baudrate(115_200.bps());
+    let (tx, rx) = buffers.usart2(board.vcp, config, &board.clocks, dma1.6, dma1.5).unwrap();
+    critical_section::with(|cs| {
+        TX.borrow_ref_mut(cs).replace(tx);
+        RX.borrow_ref_mut(cs).replace(rx);
+    });
+    // SAFETY: the handlers only touch TX and RX, which are set up above.
+    unsafe {
+        NVIC::unmask(Interrupt::USART2);
+        NVIC::unmask(Interrupt::DMA1_STREAM5);
+        NVIC::unmask(Interrupt::DMA1_STREAM6);
+    }
+
+    let mut rate_ms = RATE_MS.load(Ordering::Relaxed);
+    let mut tick = timebase.periodic(Duration::from_millis(rate_ms as u64));
+    let mut count: u32 = 0;
+    let mut dropped: u32 = 0;
+
+    loop {
+        if RATE_MS.load(Ordering::Relaxed) != rate_ms {
+            rate_ms = RATE_MS.load(Ordering::Relaxed);
+            tick = timebase.periodic(Duration::from_millis(rate_ms as u64));
+        }
+
+        if tick.poll(timebase.now()) {
+            // Format the frame, then queue it and carry on: the DMA sends it.
+            let mut frame: String<64> = String::new();
+            write!(frame, "t={} n={} dropped={}\r\n", timebase.now().as_millis(), count, dropped).ok();
+            let queued = critical_section::with(|cs| match TX.borrow_ref_mut(cs).as_mut() {
+                Some(tx) => tx.send(frame.as_bytes()).is_ok(),
+                None => false,
+            });
+            if queued {
+                count += 1;
+            } else {
+                dropped += 1; // still sending the last two frames, the rate is too high
+            }
+        }
+
+        // The control loop would run here.
+    }
+}
+
+
+// Parse one received chunk, e.g. "rate 20".
+fn parse(chunk: &[u8]) {
+    let Ok(text) = core::str::from_utf8(chunk) else { return };
+    if let Some(arg) = text.trim().strip_prefix("rate ")
+        && let Ok(ms) = arg.parse::<u32>()
+    {
+        RATE_MS.store(ms.max(1), Ordering::Relaxed);
+    }
+}
+
+// Line idle: a chunk has arrived.
+#[interrupt]
+fn USART2() {
+    critical_section::with(|cs| {
+        if let Some(rx) = RX.borrow_ref_mut(cs).as_mut() {
+            rx.on_interrupt(parse);
+        }
+    });
+}
+
+// Receive ring half or completely filled: drain it before the stream comes round.
+#[interrupt]
+fn DMA1_STREAM5() {
+    critical_section::with(|cs| {
+        if let Some(rx) = RX.borrow_ref_mut(cs).as_mut() {
+            rx.on_interrupt(parse);
+        }
+    });
+}
+
+// Frame sent: start the queued one.
+#[interrupt]
+fn DMA1_STREAM6() {
+    critical_section::with(|cs| {
+        if let Some(tx) = TX.borrow_ref_mut(cs).as_mut() {
+            tx.on_interrupt();
+        }
+    });
+}
@@ -1,55 +1,84 @@
+// Compiler directives
 #![no_std]
 #![no_main]
 
+// Libraries
+// Generic
+use core::cell::RefCell;
+use cortex_m::peripheral::NVIC;
 use cortex_m_rt::entry;
+use critical_section::Mutex;
 use defmt_rtt as _;
 use panic_probe as _;
-use core::fmt::Write;
+
+// UART Specific
+use embedded_io::{Read, Write}; // Byte-stream traits of the buffered port.
+use heapless::String; // fixed-capacity string
 use stm32f4xx_hal::{
-    pac,
-    prelude::*,
-    serial::{config::Config, Serial},
+    pac::{interrupt, Interrupt, USART2},
+    serial::Serial,
 };
-use nb::block;
-use heapless::String;
+
+// This library
+use library::buffered_serial::{SerialBuffers, SerialInterrupt};
+use library::{Board, BufferedSerial, ClockProfile}; // Board pins and the buffered serial port.
+
+
+
+// Interrupt half of the serial port, handed to the USART2 handler.
+static SERIAL_IRQ: Mutex<RefCell<Option<SerialInterrupt<Serial<USART2>, 256, 256>>>> =
+    Mutex::new(RefCell::new(None));
 
 #[entry]
 fn main() -> ! {
-    let dp = pac::Peripherals::take().unwrap();
-    let gpioa = dp.GPIOA.split();
-
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.freeze();
+    // ========================== Set-up ==========================
+    // Take ownership of the board and configure clocks
+    let board = Board::take(ClockProfile::Hsi16).unwrap();
 
-    let tx = gpioa.pa2.into_alternate();
-    let rx = gpioa.pa3.into_alternate();
-    let serial_config = Config::default().baudrate(115_200.bps());
-    let serial = Serial::new(dp.USART2, (tx, rx), serial_config, &clocks).unwrap();
+    // Received bytes wait in a ring buffer until the loop gets to them, so a burst is not lost.
+    let buffers = cortex_m::singleton!(: SerialBuffers<256, 256> = SerialBuffers::new()).unwrap();
+    let (mut serial, irq) = BufferedSerial::usart2(board.vcp, buffers);
+    critical_section::with(|cs| SERIAL_IRQ.borrow_ref_mut(cs).replace(irq));
+    // SAFETY: the handler only touches SERIAL_IRQ, which is set up above.
+    unsafe { NVIC::unmask(Interrupt::USART2) };
 
-    let (mut tx, mut rx) = serial.split();
+    let mut line: String<64> = String::new();
+    let mut chunk = [0u8; 32];
+    let mut errors = serial.errors();
 
-    let ms: u32 = 8_000;
-    let mut buf: String<64> = String::new();
 
+    // ========================== Main Loop ==========================
     loop {
-        if let Ok(byte) = block!(rx.read()) {
+        let count = serial.read(&mut chunk).unwrap();
+        for &byte in &chunk[..count] {
             if byte == b'\n' || byte == b'\r' {
-                let trimmed = buf.trim();
+                let trimmed = line.trim();
                 if let Some(arg) = trimmed.strip_prefix("thunder ") {
                     if let Ok(val) = arg.parse::<u32>() {
-                        writeln!(tx, "LIGHTNING {}\r", val).ok();
+                        write!(serial, "LIGHTNING {}\r\n", val).ok();
                     } else {
-                        writeln!(tx, "Invalid number\r").ok();
+                        write!(serial, "Invalid number\r\n").ok();
                     }
                 }
-                buf.clear();
-            } else {
-                if buf.push(byte as char).is_err() {
-                    buf.clear(); // reset buffer if overflow
-                }
+                line.clear();
+            } else if line.push(byte as char).is_err() {
+                line.clear(); // reset buffer if overflow
             }
         }
 
-        cortex_m::asm::delay(500 * ms);
+        // Report receive errors as they happen; the stream carries on regardless.
+        if serial.errors() != errors {
+            errors = serial.errors();
+            defmt::warn!("UART errors: {}", errors);
+        }
     }
 }
+
+#[interrupt]
+fn USART2() {
+    critical_section::with(|cs| {
+        if let Some(irq) = SERIAL_IRQ.borrow_ref_mut(cs).as_mut() {
+            irq.on_interrupt();
+        }
+    });
+}
@@ -0,0 +1,382 @@
+// Unit-typed angles.
+//
+// `RawAngle` is the AS5600's 12-bit count and `Turns` a 16-bit binary fraction of
+// a turn (Q0.16), so a raw angle converts to `Turns` by a shift without loss. Both
+// wrap on overflow like the shaft does, and are integer-only for use in interrupt
+// handlers. `Degrees` and `Radians` are f32 for display and control maths.
+
+// Imports
+use core::f32::consts::TAU;
+use core::ops::{Add, Neg, Sub};
+
+/// Counts per revolution of the 12-bit AS5600 angle.
+pub const COUNTS_PER_TURN: u16 = 4096;
+
+/// Shortest signed step from `previous` to `current` 12-bit angle, in counts (-2048..=2047).
+pub fn wrap_delta(previous: u16, current: u16) -> i16 {
+    wrap_delta_in(previous as u32, current as u32, COUNTS_PER_TURN as u32) as i16
+}
+
+/// Shortest signed step from `previous` to `current` on a circle of `counts_per_turn` counts.
+pub fn wrap_delta_in(previous: u32, current: u32, counts_per_turn: u32) -> i32 {
+    let counts = counts_per_turn as i64;
+    let delta = (current as i64 - previous as i64).rem_euclid(counts);
+    if delta >= counts - counts / 2 {
+        (delta - counts) as i32
+    } else {
+        delta as i32
+    }
+}
+
+/// 12-bit AS5600 angle, 4096 counts per turn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, defmt::Format)]
+pub struct RawAngle(u16);
+
+impl RawAngle {
+    pub const ZERO: Self = Self(0);
+
+    /// Creates an angle from a count, keeping the low 12 bits so it wraps at a full turn.
+    pub const fn new(counts: u16) -> Self {
+        Self(counts & (COUNTS_PER_TURN - 1))
+    }
+
+    /// The count, 0..=4095.
+    pub const fn counts(self) -> u16 {
+        self.0
+    }
+
+    /// Shortest signed step from `from` to `self`, in counts (-2048..=2047).
+    pub fn diff(self, from: Self) -> i16 {
+        wrap_delta(from.0, self.0)
+    }
+
+    /// The angle as a fraction of a turn, without loss.
+    pub const fn to_turns(self) -> Turns {
+        Turns(self.0 << 4)
+    }
+
+    /// The angle in degrees, exact in f32.
+    pub fn to_degrees(self) -> Degrees {
+        Degrees(self.0 as f32 * 360.0 / COUNTS_PER_TURN as f32)
+    }
+
+    /// The angle in radians.
+    pub fn to_radians(self) -> Radians {
+        Radians(self.0 as f32 * TAU / COUNTS_PER_TURN as f32)
+    }
+}
+
+impl Add for RawAngle {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for RawAngle {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl Neg for RawAngle {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(self.0.wrapping_neg())
+    }
+}
+
+/// Fixed-point angle in turns, Q0.16: 65536 steps per turn.
+///
+/// Addition and subtraction wrap at a full turn, and `diff` takes the short way
+/// round, all in integer arithmetic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, defmt::Format)]
+pub struct Turns(u16);
+
+impl Turns {
+    pub const ZERO: Self = Self(0);
+    pub const QUARTER: Self = Self(1 << 14);
+    pub const HALF: Self = Self(1 << 15);
+
+    /// Creates an angle from its Q0.16 bits.
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    /// The Q0.16 bits.
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Shortest signed step from `from` to `self`, in Q0.16 turns (-32768..=32767).
+    pub const fn diff(self, from: Self) -> i16 {
+        self.0.wrapping_sub(from.0) as i16
+    }
+
+    /// The nearest raw angle.
+    pub const fn to_raw(self) -> RawAngle {
+        RawAngle::new(self.0.wrapping_add(1 << 3) >> 4)
+    }
+
+    /// The angle in degrees, exact in f32.
+    pub fn to_degrees(self) -> Degrees {
+        Degrees(self.0 as f32 * 360.0 / 65536.0)
+    }
+
+    /// The angle in radians.
+    pub fn to_radians(self) -> Radians {
+        Radians(self.0 as f32 * TAU / 65536.0)
+    }
+
+    // Round a float number of turns to the nearest step, wrapped into one turn.
+    fn from_unit(turns: f32) -> Self {
+        let steps = wrap_unit(turns) * 65536.0 + 0.5;
+        Self(steps as u32 as u16)
+    }
+}
+
+impl Add for Turns {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl Sub for Turns {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl Neg for Turns {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(self.0.wrapping_neg())
+    }
+}
+
+/// Angle in degrees. Not wrapped, so it can hold multi-turn positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Degrees(pub f32);
+
+impl Degrees {
+    /// The angle wrapped into 0.0..360.0.
+    pub fn wrapped(self) -> Self {
+        Self(wrap_unit(self.0 / 360.0) * 360.0)
+    }
+
+    /// Shortest signed step from `from` to `self`, in -180.0..180.0.
+    pub fn diff(self, from: Self) -> Self {
+        Self(wrap_unit((self.0 - from.0) / 360.0 + 0.5) * 360.0 - 180.0)
+    }
+
+    /// The nearest fixed-point angle, wrapped into one turn.
+    pub fn to_turns(self) -> Turns {
+        Turns::from_unit(self.0 / 360.0)
+    }
+
+    /// The angle in radians.
+    pub fn to_radians(self) -> Radians {
+        Radians(self.0 * TAU / 360.0)
+    }
+}
+
+impl Add for Degrees {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Degrees {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Degrees {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl defmt::Format for Degrees {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=f32}°", self.0)
+    }
+}
+
+/// Angle in radians. Not wrapped, so it can hold multi-turn positions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Radians(pub f32);
+
+impl Radians {
+    /// The angle wrapped into 0.0..2π.
+    pub fn wrapped(self) -> Self {
+        Self(wrap_unit(self.0 / TAU) * TAU)
+    }
+
+    /// Shortest signed step from `from` to `self`, in -π..π.
+    pub fn diff(self, from: Self) -> Self {
+        Self((wrap_unit((self.0 - from.0) / TAU + 0.5) - 0.5) * TAU)
+    }
+
+    /// The nearest fixed-point angle, wrapped into one turn.
+    pub fn to_turns(self) -> Turns {
+        Turns::from_unit(self.0 / TAU)
+    }
+
+    /// The angle in degrees.
+    pub fn to_degrees(self) -> Degrees {
+        Degrees(self.0 * 360.0 / TAU)
+    }
+}
+
+impl Add for Radians {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Radians {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Radians {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl defmt::Format for Radians {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=f32} rad", self.0)
+    }
+}
+
+// Conversions
+impl From<RawAngle> for Turns {
+    fn from(angle: RawAngle) -> Self {
+        angle.to_turns()
+    }
+}
+
+impl From<RawAngle> for Degrees {
+    fn from(angle: RawAngle) -> Self {
+        angle.to_degrees()
+    }
+}
+
+impl From<RawAngle> for Radians {
+    fn from(angle: RawAngle) -> Self {
+        angle.to_radians()
+    }
+}
+
+impl From<Turns> for Degrees {
+    fn from(angle: Turns) -> Self {
+        angle.to_degrees()
+    }
+}
+
+impl From<Turns> for Radians {
+    fn from(angle: Turns) -> Self {
+        angle.to_radians()
+    }
+}
+
+impl From<Degrees> for Radians {
+    fn from(angle: Degrees) -> Self {
+        angle.to_radians()
+    }
+}
+
+impl From<Radians> for Degrees {
+    fn from(angle: Radians) -> Self {
+        angle.to_degrees()
+    }
+}
+
+// Fractional part of `x`, in 0.0..1.0. core has no `f32::rem_euclid`.
+fn wrap_unit(x: f32) -> f32 {
+    let frac = x - (x as i64) as f32;
+    let frac = if frac < 0.0 { frac + 1.0 } else { frac };
+    if frac >= 1.0 { 0.0 } else { frac }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn wrap_delta_takes_shortest_path() {
+        assert_eq!(wrap_delta(4095, 0), 1);
+        assert_eq!(wrap_delta(0, 4095), -1);
+        assert_eq!(wrap_delta(100, 300), 200);
+        assert_eq!(wrap_delta(0, 2048), -2048);
+        assert_eq!(wrap_delta(0, 2047), 2047);
+        assert_eq!(wrap_delta_in(0, 3, 7), 3);
+        assert_eq!(wrap_delta_in(0, 4, 7), -3);
+    }
+
+    #[test]
+    fn raw_conversions_are_lossless() {
+        for counts in 0..COUNTS_PER_TURN {
+            let raw = RawAngle::new(counts);
+            assert_eq!(raw.to_turns().to_raw(), raw);
+            assert_eq!(raw.to_degrees().to_turns(), raw.to_turns());
+            assert_eq!(raw.to_turns().to_degrees(), raw.to_degrees());
+        }
+        assert_eq!(RawAngle::new(4095).to_degrees(), Degrees(4095.0 * 360.0 / 4096.0));
+        assert_eq!(RawAngle::new(1024).to_turns(), Turns::QUARTER);
+    }
+
+    #[test]
+    fn raw_arithmetic_wraps() {
+        assert_eq!(RawAngle::new(4096 + 5), RawAngle::new(5));
+        assert_eq!(RawAngle::new(4000) + RawAngle::new(200), RawAngle::new(104));
+        assert_eq!(RawAngle::new(100) - RawAngle::new(200), RawAngle::new(3996));
+        assert_eq!(-RawAngle::new(1), RawAngle::new(4095));
+        assert_eq!(RawAngle::new(0).diff(RawAngle::new(4095)), 1);
+        assert_eq!(RawAngle::new(4095).diff(RawAngle::new(0)), -1);
+    }
+
+    #[test]
+    fn turns_wrap_and_take_shortest_path() {
+        assert_eq!(Turns::HALF + Turns::HALF, Turns::ZERO);
+        assert_eq!(Turns::ZERO - Turns::QUARTER, Turns::from_bits(0xC000));
+        assert_eq!(Turns::from_bits(10).diff(Turns::from_bits(65530)), 16);
+        assert_eq!(Turns::from_bits(65530).diff(Turns::from_bits(10)), -16);
+        assert_eq!(Turns::HALF.diff(Turns::ZERO), i16::MIN);
+        // Rounds to the nearest count, wrapping the top half-count to zero.
+        assert_eq!(Turns::from_bits(0xFFF8).to_raw(), RawAngle::ZERO);
+        assert_eq!(Turns::from_bits(0x0017).to_raw(), RawAngle::new(1));
+    }
+
+    #[test]
+    fn float_angles_wrap_and_diff() {
+        assert!(close(Degrees(-90.0).wrapped().0, 270.0));
+        assert!(close(Degrees(725.0).wrapped().0, 5.0));
+        assert!(close(Degrees(10.0).diff(Degrees(350.0)).0, 20.0));
+        assert!(close(Degrees(350.0).diff(Degrees(10.0)).0, -20.0));
+        assert!(close(Radians(-PI / 2.0).wrapped().0, 1.5 * PI));
+        assert!(close(Radians(0.1).diff(Radians(TAU - 0.1)).0, 0.2));
+        assert_eq!(Degrees(-90.0).to_turns(), Turns::from_bits(0xC000));
+        assert_eq!(Radians(PI).to_turns(), Turns::HALF);
+        assert!(close(Degrees::from(Radians(PI)).0, 180.0));
+    }
+}
@@ -0,0 +1,361 @@
+// Async twin of `As5600` on `embedded_hal_async::i2c::I2c`, enabled with the `async` feature.
+//
+// Lets RTIC 2 async tasks await encoder reads instead of blocking their priority
+// level. OTP burning stays blocking only, as it is a one-off bench operation.
+
+// Imports
+use core::convert::Infallible;
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::i2c::I2c;
+
+use super::shared::{self, State};
+use super::{reg, Config, Direction, Error, NoDirPin, Orientation, Status};
+use crate::angle::{Degrees, RawAngle, Turns};
+
+// Driver struct
+pub struct As5600Async<I2C, DIR = NoDirPin> {
+    i2c: I2C,
+    state: State<DIR>,
+}
+
+// Constructor
+impl<I2C, E> As5600Async<I2C>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Fixed 7-bit I2C address of the AS5600.
+    pub const DEFAULT_ADDR: u8 = shared::DEFAULT_ADDR;
+
+    // Constructor
+    pub fn new(i2c: I2C) -> Self {
+        Self { i2c, state: State::new() }
+    }
+
+    /// Takes ownership of the pin wired to DIR, see `As5600::with_dir_pin`.
+    pub fn with_dir_pin<DIR>(self, pin: DIR) -> As5600Async<I2C, DIR>
+    where
+        DIR: OutputPin<Error = Infallible>,
+    {
+        As5600Async { i2c: self.i2c, state: self.state.with_dir_pin(pin) }
+    }
+}
+
+// Direction control
+impl<I2C, DIR, E> As5600Async<I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+    DIR: OutputPin<Error = Infallible>,
+{
+    /// Sets which way of turning increases the angle, see `As5600::set_direction`.
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.state.set_direction(direction);
+    }
+}
+
+// Driver implementation
+impl<I2C, DIR, E> As5600Async<I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Enables checked reads: angle reads first read STATUS and fail on a magnet problem.
+    pub fn with_status_check(mut self, enabled: bool) -> Self {
+        self.state.check_status = enabled;
+        self
+    }
+
+    /// Reads the unscaled 12-bit angle from RAW ANGLE, as the sensor reports it.
+    pub async fn read_raw_angle(&mut self) -> Result<RawAngle, Error<E>> {
+        self.check_magnet_if_enabled().await?;
+        Ok(RawAngle::new(self.read_u12(reg::RAW_ANGLE).await?))
+    }
+
+    /// Reads RAW ANGLE with the software zero and direction applied.
+    pub async fn read_position(&mut self) -> Result<RawAngle, Error<E>> {
+        Ok(self.read_turns().await?.to_raw())
+    }
+
+    /// Reads the 12-bit angle scaled by ZPOS/MPOS/MANG from ANGLE.
+    /// The software zero and direction do not apply, ZPOS/MPOS set its range.
+    pub async fn read_angle(&mut self) -> Result<RawAngle, Error<E>> {
+        self.check_magnet_if_enabled().await?;
+        Ok(RawAngle::new(self.read_u12(reg::ANGLE).await?))
+    }
+
+    /// Reads the position as a fixed-point fraction of a turn, for use without floats.
+    pub async fn read_turns(&mut self) -> Result<Turns, Error<E>> {
+        let raw = self.read_raw_angle().await?;
+        Ok(self.state.position(raw))
+    }
+
+    /// Reads the position in degrees (0.0 - 360.0).
+    pub async fn read_degrees(&mut self) -> Result<Degrees, Error<E>> {
+        Ok(self.read_turns().await?.to_degrees())
+    }
+
+    /// Makes the current shaft position read as zero.
+    pub async fn set_zero_here(&mut self) -> Result<(), Error<E>> {
+        self.state.orientation.zero = self.read_raw_angle().await?.to_turns();
+        Ok(())
+    }
+
+    /// Sets the sensor angle that reads as zero.
+    pub fn set_zero(&mut self, zero: Turns) {
+        self.state.orientation.zero = zero;
+    }
+
+    /// The direction that increases the angle.
+    pub fn direction(&self) -> Direction {
+        self.state.direction
+    }
+
+    /// The software zero and inversion in use.
+    pub fn orientation(&self) -> Orientation {
+        self.state.orientation
+    }
+
+    /// Reads the magnet status bits.
+    pub async fn read_status(&mut self) -> Result<Status, Error<E>> {
+        Ok(Status::from_bits(self.read_u8(reg::STATUS).await?))
+    }
+
+    /// Reads STATUS and fails with the matching magnet error, if any.
+    pub async fn check_magnet(&mut self) -> Result<(), Error<E>> {
+        shared::check_status(self.read_u8(reg::STATUS).await?)
+    }
+
+    /// Reads the automatic gain control value.
+    pub async fn read_agc(&mut self) -> Result<u8, Error<E>> {
+        self.read_u8(reg::AGC).await
+    }
+
+    /// Reads the 12-bit CORDIC magnitude.
+    pub async fn read_magnitude(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::MAGNITUDE).await
+    }
+
+    /// Reads how many times ZPOS/MPOS has been burned (0..=3).
+    pub async fn read_zmco(&mut self) -> Result<u8, Error<E>> {
+        Ok(shared::decode_zmco(self.read_u8(reg::ZMCO).await?))
+    }
+
+    /// Reads the start position (ZPOS).
+    pub async fn read_zero_position(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::ZPOS).await
+    }
+
+    /// Writes the start position (ZPOS). Fails with `InvalidConfig` above 4095.
+    pub async fn set_zero_position(&mut self, position: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_u12(reg::ZPOS, position)?).await
+    }
+
+    /// Reads the stop position (MPOS).
+    pub async fn read_max_position(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::MPOS).await
+    }
+
+    /// Writes the stop position (MPOS). Fails with `InvalidConfig` above 4095.
+    pub async fn set_max_position(&mut self, position: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_u12(reg::MPOS, position)?).await
+    }
+
+    /// Reads the maximum angle (MANG).
+    pub async fn read_max_angle(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::MANG).await
+    }
+
+    /// Writes the maximum angle (MANG). Fails with `InvalidConfig` above 4095.
+    pub async fn set_max_angle(&mut self, angle: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_u12(reg::MANG, angle)?).await
+    }
+
+    /// Reads the 14-bit CONF register.
+    pub async fn read_conf(&mut self) -> Result<u16, Error<E>> {
+        Ok(shared::decode_conf(self.read_pair(reg::CONF).await?))
+    }
+
+    /// Writes the CONF register. Fails with `InvalidConfig` above 14 bits.
+    pub async fn write_conf(&mut self, conf: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_conf(conf)?).await
+    }
+
+    /// Reads and decodes the CONF register. Fails with `InvalidConfig` on the reserved OUTS code.
+    pub async fn read_config(&mut self) -> Result<Config, Error<E>> {
+        shared::decode_config(self.read_conf().await?)
+    }
+
+    /// Writes all CONF settings.
+    pub async fn write_config(&mut self, config: Config) -> Result<(), Error<E>> {
+        self.write_conf(config.bits()).await
+    }
+
+    /// Reads CONF, applies `f` and writes the result back. Returns the written settings.
+    pub async fn modify_config<F>(&mut self, f: F) -> Result<Config, Error<E>>
+    where
+        F: FnOnce(Config) -> Config,
+    {
+        let config = f(self.read_config().await?);
+        self.write_config(config).await?;
+        Ok(config)
+    }
+
+    // Release peripheral
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    // Release peripheral and DIR pin
+    pub fn release_parts(self) -> (I2C, Option<DIR>) {
+        (self.i2c, self.state.dir)
+    }
+
+    // Register access helpers
+    async fn check_magnet_if_enabled(&mut self) -> Result<(), Error<E>> {
+        if self.state.check_status {
+            self.check_magnet().await?;
+        }
+        Ok(())
+    }
+
+    async fn read_u8(&mut self, register: u8) -> Result<u8, Error<E>> {
+        let mut buf = [0u8; 1];
+        self.i2c.write_read(self.state.address, &[register], &mut buf).await.map_err(Error::Bus)?;
+        Ok(buf[0])
+    }
+
+    async fn read_pair(&mut self, register: u8) -> Result<[u8; 2], Error<E>> {
+        let mut buf = [0u8; 2];
+        self.i2c.write_read(self.state.address, &[register], &mut buf).await.map_err(Error::Bus)?;
+        Ok(buf)
+    }
+
+    async fn read_u12(&mut self, register: u8) -> Result<u16, Error<E>> {
+        Ok(shared::decode_u12(self.read_pair(register).await?))
+    }
+
+    async fn write(&mut self, bytes: [u8; 3]) -> Result<(), Error<E>> {
+        self.i2c.write(self.state.address, &bytes).await.map_err(Error::Bus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::as5600::config::Hysteresis;
+    use crate::mock::{I2cMock, Transaction};
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use embedded_hal::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+    const ADDR: u8 = As5600Async::<I2cMock>::DEFAULT_ADDR;
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    // Minimal executor for futures that never wait.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    // Register file answering every transaction immediately.
+    struct Registers {
+        regs: [u8; 256],
+        pointer: u8,
+    }
+
+    impl ErrorType for Registers {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Registers {
+        async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            assert_eq!(address, 0x36);
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => {
+                        self.pointer = bytes[0];
+                        for (i, &byte) in bytes[1..].iter().enumerate() {
+                            self.regs[self.pointer as usize + i] = byte;
+                        }
+                    }
+                    Operation::Read(buf) => {
+                        for (i, byte) in buf.iter_mut().enumerate() {
+                            *byte = self.regs[self.pointer as usize + i];
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reads_raw_angle_and_scaled_angle() {
+        let mut regs = [0u8; 256];
+        regs[reg::RAW_ANGLE as usize..][..2].copy_from_slice(&[0xF8, 0x00]);
+        regs[reg::ANGLE as usize..][..2].copy_from_slice(&[0x04, 0x00]);
+        let mut sensor = As5600Async::new(Registers { regs, pointer: 0 });
+
+        assert_eq!(block_on(sensor.read_raw_angle()), Ok(RawAngle::new(0x800)));
+        assert_eq!(block_on(sensor.read_angle()), Ok(RawAngle::new(0x400)));
+        assert_eq!(block_on(sensor.read_degrees()), Ok(Degrees(180.0)));
+    }
+
+    #[test]
+    fn modify_config_round_trips_through_conf() {
+        let mut sensor = As5600Async::new(Registers { regs: [0; 256], pointer: 0 });
+        let written = block_on(sensor.modify_config(|c| c.hysteresis(Hysteresis::Lsb2))).unwrap();
+
+        assert_eq!(written.bits(), 0b1000);
+        assert_eq!(block_on(sensor.read_config()), Ok(written));
+    }
+
+    #[test]
+    fn status_check_reads_status_before_angle() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::STATUS], &[0x20]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x23]),
+            Transaction::write_read(ADDR, &[reg::STATUS], &[0x20]),
+            Transaction::write_read(ADDR, &[reg::ANGLE], &[0x03, 0x21]),
+        ];
+        let mut sensor = As5600Async::new(I2cMock::new(&script)).with_status_check(true);
+        assert_eq!(block_on(sensor.read_raw_angle()), Ok(RawAngle::new(0x123)));
+        assert_eq!(block_on(sensor.read_angle()), Ok(RawAngle::new(0x321)));
+        sensor.release().done();
+    }
+
+    #[test]
+    fn magnet_problems_stop_angle_reads() {
+        for (status, error) in [
+            (0x00, Error::MagnetNotDetected),
+            (0x30, Error::MagnetTooWeak),
+            (0x28, Error::MagnetTooStrong),
+        ] {
+            let script = [Transaction::write_read(ADDR, &[reg::STATUS], core::slice::from_ref(&status))];
+            let mut sensor = As5600Async::new(I2cMock::new(&script)).with_status_check(true);
+            assert_eq!(block_on(sensor.read_degrees()), Err(error));
+            sensor.release().done();
+        }
+    }
+
+    #[test]
+    fn bus_errors_propagate() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0, 0]).nack(),
+            Transaction::write(ADDR, &[reg::ZPOS, 0x01, 0x00]).nack(),
+            Transaction::write_read(ADDR, &[reg::CONF], &[0, 0]).nack(),
+        ];
+        let mut sensor = As5600Async::new(I2cMock::new(&script));
+        assert_eq!(block_on(sensor.read_raw_angle()), Err(Error::Bus(NACK)));
+        assert_eq!(block_on(sensor.set_zero_position(0x100)), Err(Error::Bus(NACK)));
+        // A failed read must not be followed by the write.
+        assert_eq!(block_on(sensor.modify_config(|c| c)), Err(Error::Bus(NACK)));
+        // Out of range values fail before touching the bus.
+        assert_eq!(block_on(sensor.set_max_angle(0x1000)), Err(Error::InvalidConfig));
+        sensor.release().done();
+    }
+}
@@ -0,0 +1,367 @@
+// Nonlinearity calibration.
+//
+// An off-centre magnet gives the AS5600 an error that repeats every turn. A
+// reference sweep, from a stepper, a finer encoder or a host tool, gives the
+// correction at N evenly spaced points of the measured angle. Reads are corrected
+// by linear interpolation between the two nearest points, in integer `Turns`
+// arithmetic. The table serializes to a small checksummed blob for flash.
+
+// Imports
+use embedded_hal::i2c::I2c;
+
+use super::{As5600, Error, NoDirPin};
+use crate::angle::{Degrees, RawAngle, Turns, COUNTS_PER_TURN};
+use crate::encoder::RotaryEncoder;
+
+// Serialized format: magic, version, N (u16 LE), N corrections (i16 LE), CRC-16 (LE).
+const MAGIC: [u8; 3] = *b"ASC";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 6;
+const CRC_LEN: usize = 2;
+
+/// Errors from building, storing or loading a calibration table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum CalibrationError {
+    /// The sweep left the table point with this index without samples.
+    MissingPoint(usize),
+    /// The output buffer is shorter than `Calibration::BYTE_LEN`.
+    BufferTooSmall,
+    /// The data does not start with a calibration header of this version.
+    BadHeader,
+    /// The data holds a table with a different number of points.
+    WrongLength,
+    /// The data is corrupted.
+    ChecksumMismatch,
+}
+
+/// Correction table with `N` points, evenly spaced over one turn of measured angle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration<const N: usize> {
+    // Reference minus measured angle at each point, in Q0.16 turns.
+    corrections: [i16; N],
+}
+
+impl<const N: usize> Calibration<N> {
+    /// Size of the serialized table in bytes.
+    pub const BYTE_LEN: usize = HEADER_LEN + 2 * N + CRC_LEN;
+
+    /// Creates a table from corrections in Q0.16 turns, point `i` at `i / N` of a turn.
+    pub const fn from_corrections(corrections: [i16; N]) -> Self {
+        const { assert!(N > 0, "a calibration table needs at least one point") };
+        Self { corrections }
+    }
+
+    /// A table that leaves angles unchanged.
+    pub const fn identity() -> Self {
+        const { assert!(N > 0, "a calibration table needs at least one point") };
+        Self { corrections: [0; N] }
+    }
+
+    /// Corrections in Q0.16 turns.
+    pub fn corrections(&self) -> &[i16; N] {
+        &self.corrections
+    }
+
+    /// Correction for `measured`, interpolated between the two nearest points.
+    pub fn correction(&self, measured: Turns) -> i16 {
+        let scaled = measured.bits() as u32 * N as u32;
+        let index = (scaled >> 16) as usize;
+        let fraction = (scaled & 0xFFFF) as i64;
+        let a = self.corrections[index] as i64;
+        let b = self.corrections[(index + 1) % N] as i64;
+        // Neighbouring points can be up to 65535 apart, so the product needs 64 bits.
+        (a + (((b - a) * fraction) >> 16)) as i16
+    }
+
+    /// Corrects a measured angle.
+    pub fn apply(&self, measured: Turns) -> Turns {
+        Turns::from_bits(measured.bits().wrapping_add_signed(self.correction(measured)))
+    }
+
+    /// Writes the table to the start of `buf` and returns the number of bytes written.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, CalibrationError> {
+        let buf = buf.get_mut(..Self::BYTE_LEN).ok_or(CalibrationError::BufferTooSmall)?;
+        buf[..3].copy_from_slice(&MAGIC);
+        buf[3] = VERSION;
+        buf[4..HEADER_LEN].copy_from_slice(&(N as u16).to_le_bytes());
+        for (chunk, correction) in buf[HEADER_LEN..].chunks_exact_mut(2).zip(&self.corrections) {
+            chunk.copy_from_slice(&correction.to_le_bytes());
+        }
+        let crc = crc16(&buf[..Self::BYTE_LEN - CRC_LEN]);
+        buf[Self::BYTE_LEN - CRC_LEN..].copy_from_slice(&crc.to_le_bytes());
+        Ok(Self::BYTE_LEN)
+    }
+
+    /// Reads a table written by `to_bytes`. Bytes after the table are ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CalibrationError> {
+        if bytes.len() < HEADER_LEN || bytes[..3] != MAGIC || bytes[3] != VERSION {
+            return Err(CalibrationError::BadHeader);
+        }
+        if u16::from_le_bytes([bytes[4], bytes[5]]) as usize != N || bytes.len() < Self::BYTE_LEN {
+            return Err(CalibrationError::WrongLength);
+        }
+        let (body, crc) = bytes[..Self::BYTE_LEN].split_at(Self::BYTE_LEN - CRC_LEN);
+        if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Err(CalibrationError::ChecksumMismatch);
+        }
+        let mut corrections = [0; N];
+        for (correction, chunk) in corrections.iter_mut().zip(body[HEADER_LEN..].chunks_exact(2)) {
+            *correction = i16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+        Ok(Self { corrections })
+    }
+}
+
+/// Collects a reference sweep into a `Calibration`.
+///
+/// Each sample is the angle the AS5600 measured and the true angle from the
+/// reference. Samples are averaged into the nearest table point, so the sweep
+/// must cover every point; several passes in both directions average out
+/// hysteresis and noise.
+#[derive(Clone, Copy, Debug)]
+pub struct CalibrationBuilder<const N: usize> {
+    sums: [i32; N],
+    counts: [u16; N],
+}
+
+impl<const N: usize> CalibrationBuilder<N> {
+    // Constructor
+    pub fn new() -> Self {
+        const { assert!(N > 0, "a calibration table needs at least one point") };
+        Self { sums: [0; N], counts: [0; N] }
+    }
+
+    /// Adds one sample: `measured` read from the sensor while the shaft was at `reference`.
+    ///
+    /// A point stops taking samples once it holds `u16::MAX` of them.
+    pub fn add_sample(&mut self, measured: Turns, reference: Turns) {
+        let index = ((measured.bits() as u32 * N as u32 + (1 << 15)) >> 16) as usize % N;
+        if self.counts[index] < u16::MAX {
+            self.sums[index] += reference.diff(measured) as i32;
+            self.counts[index] += 1;
+        }
+    }
+
+    /// Number of samples collected for each point.
+    pub fn counts(&self) -> &[u16; N] {
+        &self.counts
+    }
+
+    /// Averages the samples into a table. Fails if a point has no samples.
+    pub fn build(&self) -> Result<Calibration<N>, CalibrationError> {
+        let mut corrections = [0; N];
+        for (index, correction) in corrections.iter_mut().enumerate() {
+            let count = self.counts[index] as i32;
+            if count == 0 {
+                return Err(CalibrationError::MissingPoint(index));
+            }
+            // Round the mean to nearest.
+            let sum = self.sums[index];
+            let half = if sum < 0 { -count / 2 } else { count / 2 };
+            *correction = ((sum + half) / count) as i16;
+        }
+        Ok(Calibration { corrections })
+    }
+}
+
+impl<const N: usize> Default for CalibrationBuilder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An `As5600` whose angle reads are corrected by a `Calibration`.
+///
+/// The table corrects the sensor angle before the software zero and direction
+/// are applied. Switching the direction with a DIR pin mirrors the sensor angle,
+/// so the table must then be recorded with that direction.
+pub struct Calibrated<I2C, const N: usize, DIR = NoDirPin> {
+    sensor: As5600<I2C, DIR>,
+    calibration: Calibration<N>,
+}
+
+impl<I2C, DIR, E> As5600<I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Applies `calibration` to every angle read.
+    pub fn with_calibration<const N: usize>(self, calibration: Calibration<N>) -> Calibrated<I2C, N, DIR> {
+        Calibrated { sensor: self, calibration }
+    }
+}
+
+impl<I2C, DIR, E, const N: usize> Calibrated<I2C, N, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Reads the corrected raw angle, rounded to the nearest count.
+    /// The software zero and direction do not apply.
+    pub fn read_raw_angle(&mut self) -> Result<RawAngle, Error<E>> {
+        Ok(self.read_corrected()?.to_raw())
+    }
+
+    /// Reads the corrected angle with the software zero and direction applied, rounded to the nearest count.
+    pub fn read_position(&mut self) -> Result<RawAngle, Error<E>> {
+        Ok(self.read_turns()?.to_raw())
+    }
+
+    /// Reads the corrected position as a fixed-point fraction of a turn.
+    pub fn read_turns(&mut self) -> Result<Turns, Error<E>> {
+        let corrected = self.read_corrected()?;
+        Ok(self.sensor.orientation().apply(corrected))
+    }
+
+    /// Reads the corrected position in degrees (0.0 - 360.0).
+    pub fn read_degrees(&mut self) -> Result<Degrees, Error<E>> {
+        Ok(self.read_turns()?.to_degrees())
+    }
+
+    /// Makes the current shaft position read as zero.
+    pub fn set_zero_here(&mut self) -> Result<(), Error<E>> {
+        let corrected = self.read_corrected()?;
+        self.sensor.set_zero(corrected);
+        Ok(())
+    }
+
+    // Corrected sensor angle, before the software zero and direction
+    fn read_corrected(&mut self) -> Result<Turns, Error<E>> {
+        Ok(self.calibration.apply(self.sensor.read_raw_angle()?.to_turns()))
+    }
+
+    /// The calibration table in use.
+    pub fn calibration(&self) -> &Calibration<N> {
+        &self.calibration
+    }
+
+    /// Replaces the calibration table.
+    pub fn set_calibration(&mut self, calibration: Calibration<N>) {
+        self.calibration = calibration;
+    }
+
+    /// The uncorrected sensor, for configuration and diagnostics.
+    pub fn sensor(&mut self) -> &mut As5600<I2C, DIR> {
+        &mut self.sensor
+    }
+
+    // Release sensor and table
+    pub fn release(self) -> (As5600<I2C, DIR>, Calibration<N>) {
+        (self.sensor, self.calibration)
+    }
+}
+
+// Corrected counterpart of the `As5600` encoder backend.
+impl<I2C, DIR, E, const N: usize> RotaryEncoder for Calibrated<I2C, N, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    type Error = Error<E>;
+
+    fn counts_per_revolution(&self) -> u32 {
+        COUNTS_PER_TURN as u32
+    }
+
+    fn position(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.read_position()?.counts() as u32)
+    }
+}
+
+// CRC-16/CCITT-FALSE.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::as5600::reg;
+    use crate::mock::{I2cMock, Transaction};
+
+    // Misaligned magnet: 3° first-harmonic error plus a 1° offset.
+    fn measured_at(reference: Degrees) -> Degrees {
+        let error = 3.0 * reference.to_radians().0.sin() + 1.0;
+        Degrees(reference.0 + error).wrapped()
+    }
+
+    #[test]
+    fn sweep_corrects_periodic_error() {
+        let mut builder = CalibrationBuilder::<32>::new();
+        for step in 0..720 {
+            let reference = Degrees(step as f32 * 0.5);
+            builder.add_sample(measured_at(reference).to_turns(), reference.to_turns());
+        }
+        let calibration = builder.build().unwrap();
+
+        for step in 0..360 {
+            let reference = Degrees(step as f32 + 0.3);
+            let corrected = calibration.apply(measured_at(reference).to_turns()).to_degrees();
+            assert!(corrected.diff(reference).0.abs() < 0.1, "{:?} at {:?}", corrected, reference);
+        }
+    }
+
+    #[test]
+    fn interpolates_between_points_and_wraps() {
+        let calibration = Calibration::from_corrections([100, 300, -100, 0]);
+        assert_eq!(calibration.correction(Turns::ZERO), 100);
+        assert_eq!(calibration.correction(Turns::from_bits(0x2000)), 200);
+        assert_eq!(calibration.correction(Turns::QUARTER), 300);
+        assert_eq!(calibration.correction(Turns::from_bits(0xE000)), 50);
+        assert_eq!(calibration.apply(Turns::from_bits(0xFFFF)), Turns::from_bits(98));
+    }
+
+    #[test]
+    fn interpolates_across_full_range_steps() {
+        let calibration = Calibration::from_corrections([i16::MIN, i16::MAX, i16::MIN, 0]);
+        assert_eq!(calibration.correction(Turns::from_bits(0x2000)), -1);
+        assert_eq!(calibration.correction(Turns::from_bits(0x3FFF)), 32763);
+        assert_eq!(calibration.correction(Turns::from_bits(0x6000)), -1);
+        assert_eq!(calibration.correction(Turns::from_bits(0xE000)), -16384);
+    }
+
+    #[test]
+    fn missing_point_is_reported() {
+        let mut builder = CalibrationBuilder::<8>::new();
+        for point in 0..8u16 {
+            if point != 5 {
+                builder.add_sample(Turns::from_bits(point << 13), Turns::from_bits(point << 13));
+            }
+        }
+        assert_eq!(builder.build(), Err(CalibrationError::MissingPoint(5)));
+    }
+
+    #[test]
+    fn serializes_round_trip() {
+        let calibration = Calibration::from_corrections([1, -2, 300, i16::MIN, i16::MAX, 0, 7, -7]);
+        let mut buf = [0u8; Calibration::<8>::BYTE_LEN];
+        assert_eq!(calibration.to_bytes(&mut buf), Ok(24));
+        assert_eq!(Calibration::<8>::from_bytes(&buf), Ok(calibration));
+
+        assert_eq!(calibration.to_bytes(&mut buf[..23]), Err(CalibrationError::BufferTooSmall));
+        assert_eq!(Calibration::<16>::from_bytes(&buf), Err(CalibrationError::WrongLength));
+        assert_eq!(Calibration::<8>::from_bytes(&[0xFF; 24]), Err(CalibrationError::BadHeader));
+        buf[10] ^= 0x01;
+        assert_eq!(Calibration::<8>::from_bytes(&buf), Err(CalibrationError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn corrects_driver_reads() {
+        // +1/64 turn everywhere, i.e. +64 counts.
+        let calibration = Calibration::from_corrections([1024; 4]);
+        let script = [
+            Transaction::write_read(0x36, &[reg::RAW_ANGLE], &[0x0F, 0xF0]),
+            Transaction::write_read(0x36, &[reg::RAW_ANGLE], &[0x01, 0x00]),
+        ];
+        let mut encoder = As5600::new(I2cMock::new(&script)).with_calibration(calibration);
+        assert_eq!(encoder.read_raw_angle(), Ok(RawAngle::new(0x030)));
+        assert_eq!(encoder.position(), Ok(0x140));
+        encoder.release().0.release().done();
+    }
+}
@@ -0,0 +1,214 @@
+// Typed view of the 14-bit CONF register.
+
+/// Power mode (PM, bits 1:0).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerMode {
+    /// Always on, 6.5 mA.
+    Nom,
+    /// 5 ms polling, 3.4 mA.
+    Lpm1,
+    /// 20 ms polling, 1.8 mA.
+    Lpm2,
+    /// 100 ms polling, 1.5 mA.
+    Lpm3,
+}
+
+/// Output hysteresis (HYST, bits 3:2).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hysteresis {
+    Off,
+    Lsb1,
+    Lsb2,
+    Lsb3,
+}
+
+/// OUT pin stage (OUTS, bits 5:4).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputStage {
+    /// Analog, 0% to 100% of VDD.
+    AnalogFull,
+    /// Analog, 10% to 90% of VDD.
+    AnalogReduced,
+    /// Digital PWM.
+    DigitalPwm,
+}
+
+/// PWM output frequency (PWMF, bits 7:6).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PwmFrequency {
+    Hz115,
+    Hz230,
+    Hz460,
+    Hz920,
+}
+
+/// Slow filter step response delay (SF, bits 9:8).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlowFilter {
+    X16,
+    X8,
+    X4,
+    X2,
+}
+
+/// Fast filter threshold (FTH, bits 12:10).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FastFilterThreshold {
+    /// Fast filter disabled, slow filter only.
+    SlowOnly,
+    Lsb6,
+    Lsb7,
+    Lsb9,
+    Lsb18,
+    Lsb21,
+    Lsb24,
+    Lsb10,
+}
+
+/// CONF register settings. `Config::default()` matches the power-on state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub power_mode: PowerMode,
+    pub hysteresis: Hysteresis,
+    pub output_stage: OutputStage,
+    pub pwm_frequency: PwmFrequency,
+    pub slow_filter: SlowFilter,
+    pub fast_filter_threshold: FastFilterThreshold,
+    pub watchdog: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_bits(0)
+    }
+}
+
+impl Config {
+    // Builder methods
+    pub fn power_mode(mut self, power_mode: PowerMode) -> Self {
+        self.power_mode = power_mode;
+        self
+    }
+
+    pub fn hysteresis(mut self, hysteresis: Hysteresis) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn output_stage(mut self, output_stage: OutputStage) -> Self {
+        self.output_stage = output_stage;
+        self
+    }
+
+    pub fn pwm_frequency(mut self, pwm_frequency: PwmFrequency) -> Self {
+        self.pwm_frequency = pwm_frequency;
+        self
+    }
+
+    pub fn slow_filter(mut self, slow_filter: SlowFilter) -> Self {
+        self.slow_filter = slow_filter;
+        self
+    }
+
+    pub fn fast_filter_threshold(mut self, threshold: FastFilterThreshold) -> Self {
+        self.fast_filter_threshold = threshold;
+        self
+    }
+
+    pub fn watchdog(mut self, enabled: bool) -> Self {
+        self.watchdog = enabled;
+        self
+    }
+
+    /// Decodes a raw CONF value. The reserved OUTS code 0b11 reads as `DigitalPwm`.
+    pub fn from_bits(bits: u16) -> Self {
+        Self {
+            power_mode: match bits & 0b11 {
+                0 => PowerMode::Nom,
+                1 => PowerMode::Lpm1,
+                2 => PowerMode::Lpm2,
+                _ => PowerMode::Lpm3,
+            },
+            hysteresis: match (bits >> 2) & 0b11 {
+                0 => Hysteresis::Off,
+                1 => Hysteresis::Lsb1,
+                2 => Hysteresis::Lsb2,
+                _ => Hysteresis::Lsb3,
+            },
+            output_stage: match (bits >> 4) & 0b11 {
+                0 => OutputStage::AnalogFull,
+                1 => OutputStage::AnalogReduced,
+                _ => OutputStage::DigitalPwm,
+            },
+            pwm_frequency: match (bits >> 6) & 0b11 {
+                0 => PwmFrequency::Hz115,
+                1 => PwmFrequency::Hz230,
+                2 => PwmFrequency::Hz460,
+                _ => PwmFrequency::Hz920,
+            },
+            slow_filter: match (bits >> 8) & 0b11 {
+                0 => SlowFilter::X16,
+                1 => SlowFilter::X8,
+                2 => SlowFilter::X4,
+                _ => SlowFilter::X2,
+            },
+            fast_filter_threshold: match (bits >> 10) & 0b111 {
+                0 => FastFilterThreshold::SlowOnly,
+                1 => FastFilterThreshold::Lsb6,
+                2 => FastFilterThreshold::Lsb7,
+                3 => FastFilterThreshold::Lsb9,
+                4 => FastFilterThreshold::Lsb18,
+                5 => FastFilterThreshold::Lsb21,
+                6 => FastFilterThreshold::Lsb24,
+                _ => FastFilterThreshold::Lsb10,
+            },
+            watchdog: bits & (1 << 13) != 0,
+        }
+    }
+
+    /// Encodes the settings as a raw 14-bit CONF value.
+    pub fn bits(&self) -> u16 {
+        let pm = match self.power_mode {
+            PowerMode::Nom => 0,
+            PowerMode::Lpm1 => 1,
+            PowerMode::Lpm2 => 2,
+            PowerMode::Lpm3 => 3,
+        };
+        let hyst = match self.hysteresis {
+            Hysteresis::Off => 0,
+            Hysteresis::Lsb1 => 1,
+            Hysteresis::Lsb2 => 2,
+            Hysteresis::Lsb3 => 3,
+        };
+        let outs = match self.output_stage {
+            OutputStage::AnalogFull => 0,
+            OutputStage::AnalogReduced => 1,
+            OutputStage::DigitalPwm => 2,
+        };
+        let pwmf = match self.pwm_frequency {
+            PwmFrequency::Hz115 => 0,
+            PwmFrequency::Hz230 => 1,
+            PwmFrequency::Hz460 => 2,
+            PwmFrequency::Hz920 => 3,
+        };
+        let sf = match self.slow_filter {
+            SlowFilter::X16 => 0,
+            SlowFilter::X8 => 1,
+            SlowFilter::X4 => 2,
+            SlowFilter::X2 => 3,
+        };
+        let fth = match self.fast_filter_threshold {
+            FastFilterThreshold::SlowOnly => 0,
+            FastFilterThreshold::Lsb6 => 1,
+            FastFilterThreshold::Lsb7 => 2,
+            FastFilterThreshold::Lsb9 => 3,
+            FastFilterThreshold::Lsb18 => 4,
+            FastFilterThreshold::Lsb21 => 5,
+            FastFilterThreshold::Lsb24 => 6,
+            FastFilterThreshold::Lsb10 => 7,
+        };
+        let wd = self.watchdog as u16;
+
+        pm | hyst << 2 | outs << 4 | pwmf << 6 | sf << 8 | fth << 10 | wd << 13
+    }
+}
@@ -0,0 +1,572 @@
+// Imports
+use core::convert::Infallible;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+
+use crate::angle::{Degrees, RawAngle, Turns};
+use shared::State;
+
+// Modules
+pub mod calibration;
+pub mod config;
+pub mod orientation;
+pub mod otp;
+pub mod output;
+mod shared;
+#[cfg(feature = "async")]
+pub mod asynch;
+pub use calibration::{Calibrated, Calibration, CalibrationBuilder};
+pub use config::Config;
+pub use orientation::{Direction, NoDirPin, Orientation};
+pub use output::{As5600Analog, As5600Pwm};
+
+// Register map
+pub mod reg {
+    pub const ZMCO: u8 = 0x00;      // OTP burn counter (2 bits)
+    pub const ZPOS: u8 = 0x01;      // Start position (12 bits, 2 bytes)
+    pub const MPOS: u8 = 0x03;      // Stop position (12 bits, 2 bytes)
+    pub const MANG: u8 = 0x05;      // Maximum angle (12 bits, 2 bytes)
+    pub const CONF: u8 = 0x07;      // Configuration (14 bits, 2 bytes)
+    pub const STATUS: u8 = 0x0B;    // Magnet status bits
+    pub const RAW_ANGLE: u8 = 0x0C; // Unscaled angle (12 bits, 2 bytes)
+    pub const ANGLE: u8 = 0x0E;     // ZPOS/MPOS scaled angle (12 bits, 2 bytes)
+    pub const AGC: u8 = 0x1A;       // Automatic gain control
+    pub const MAGNITUDE: u8 = 0x1B; // CORDIC magnitude (12 bits, 2 bytes)
+    pub const BURN: u8 = 0xFF;      // OTP burn commands
+}
+
+/// Magnet status, read from the STATUS register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// MD: a magnet was detected.
+    pub magnet_detected: bool,
+    /// ML: the magnet is too weak (AGC at maximum gain).
+    pub magnet_too_weak: bool,
+    /// MH: the magnet is too strong (AGC at minimum gain).
+    pub magnet_too_strong: bool,
+}
+
+impl Status {
+    const MH: u8 = 1 << 3;
+    const ML: u8 = 1 << 4;
+    const MD: u8 = 1 << 5;
+
+    /// Decodes the raw STATUS register byte.
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            magnet_detected: bits & Self::MD != 0,
+            magnet_too_weak: bits & Self::ML != 0,
+            magnet_too_strong: bits & Self::MH != 0,
+        }
+    }
+
+    /// Maps the magnet bits to an error, if any.
+    pub fn check<E>(&self) -> Result<(), Error<E>> {
+        if !self.magnet_detected {
+            Err(Error::MagnetNotDetected)
+        } else if self.magnet_too_weak {
+            Err(Error::MagnetTooWeak)
+        } else if self.magnet_too_strong {
+            Err(Error::MagnetTooStrong)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Driver errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// I2C bus error, e.g. a NACK.
+    Bus(E),
+    /// STATUS MD bit is not set.
+    MagnetNotDetected,
+    /// STATUS ML bit is set, the magnet is too far away.
+    MagnetTooWeak,
+    /// STATUS MH bit is set, the magnet is too close.
+    MagnetTooStrong,
+    /// A value does not fit its register, or CONF holds a reserved code.
+    InvalidConfig,
+}
+
+// The HAL bus errors have no defmt impl, so the bus error is logged with its Debug impl.
+impl<E: core::fmt::Debug> defmt::Format for Error<E> {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            Error::Bus(e) => defmt::write!(f, "I2C bus error: {}", defmt::Debug2Format(e)),
+            Error::MagnetNotDetected => defmt::write!(f, "magnet not detected"),
+            Error::MagnetTooWeak => defmt::write!(f, "magnet too weak"),
+            Error::MagnetTooStrong => defmt::write!(f, "magnet too strong"),
+            Error::InvalidConfig => defmt::write!(f, "invalid configuration"),
+        }
+    }
+}
+
+// Driver struct
+pub struct As5600<I2C, DIR = NoDirPin> {
+    i2c: I2C,
+    state: State<DIR>,
+}
+
+// Constructor
+impl<I2C, E> As5600<I2C>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Fixed 7-bit I2C address of the AS5600.
+    pub const DEFAULT_ADDR: u8 = shared::DEFAULT_ADDR;
+
+    // Constructor
+    pub fn new(i2c: I2C) -> Self {
+        Self { i2c, state: State::new() }
+    }
+
+    /// Takes ownership of the pin wired to DIR and drives it to the current direction.
+    ///
+    /// `set_direction` then switches the direction in the chip, which also
+    /// flips the analog and PWM outputs. A software inversion set before is
+    /// handed to the chip, so the position reads the same as before.
+    pub fn with_dir_pin<DIR>(self, pin: DIR) -> As5600<I2C, DIR>
+    where
+        DIR: OutputPin<Error = Infallible>,
+    {
+        As5600 { i2c: self.i2c, state: self.state.with_dir_pin(pin) }
+    }
+}
+
+// Direction control
+impl<I2C, DIR, E> As5600<I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+    DIR: OutputPin<Error = Infallible>,
+{
+    /// Sets which way of turning increases the angle.
+    ///
+    /// Drives the DIR pin if the driver owns one, otherwise inverts in software.
+    /// The zero position stays where it is.
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.state.set_direction(direction);
+    }
+}
+
+// Driver implementation
+impl<I2C, DIR, E> As5600<I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Enables checked reads: angle reads first read STATUS and fail on a magnet problem.
+    pub fn with_status_check(mut self, enabled: bool) -> Self {
+        self.state.check_status = enabled;
+        self
+    }
+
+    /// Reads the unscaled 12-bit angle from RAW ANGLE, as the sensor reports it.
+    ///
+    /// The software zero and direction do not apply. This is the angle to feed a `CalibrationBuilder`.
+    pub fn read_raw_angle(&mut self) -> Result<RawAngle, Error<E>> {
+        self.check_magnet_if_enabled()?;
+        Ok(RawAngle::new(self.read_u12(reg::RAW_ANGLE)?))
+    }
+
+    /// Reads RAW ANGLE with the software zero and direction applied.
+    pub fn read_position(&mut self) -> Result<RawAngle, Error<E>> {
+        Ok(self.read_turns()?.to_raw())
+    }
+
+    /// Reads the 12-bit angle scaled by ZPOS/MPOS/MANG from ANGLE.
+    /// The software zero and direction do not apply, ZPOS/MPOS set its range.
+    pub fn read_angle(&mut self) -> Result<RawAngle, Error<E>> {
+        self.check_magnet_if_enabled()?;
+        Ok(RawAngle::new(self.read_u12(reg::ANGLE)?))
+    }
+
+    /// Reads the position as a fixed-point fraction of a turn, for use without floats.
+    pub fn read_turns(&mut self) -> Result<Turns, Error<E>> {
+        let raw = self.read_raw_angle()?;
+        Ok(self.state.position(raw))
+    }
+
+    /// Reads the position in degrees (0.0 - 360.0).
+    pub fn read_degrees(&mut self) -> Result<Degrees, Error<E>> {
+        Ok(self.read_turns()?.to_degrees())
+    }
+
+    /// Makes the current shaft position read as zero.
+    pub fn set_zero_here(&mut self) -> Result<(), Error<E>> {
+        self.state.orientation.zero = self.read_raw_angle()?.to_turns();
+        Ok(())
+    }
+
+    /// Sets the sensor angle that reads as zero.
+    pub fn set_zero(&mut self, zero: Turns) {
+        self.state.orientation.zero = zero;
+    }
+
+    /// The direction that increases the angle.
+    pub fn direction(&self) -> Direction {
+        self.state.direction
+    }
+
+    /// The software zero and inversion in use.
+    pub fn orientation(&self) -> Orientation {
+        self.state.orientation
+    }
+
+    /// Reads the magnet status bits.
+    pub fn read_status(&mut self) -> Result<Status, Error<E>> {
+        Ok(Status::from_bits(self.read_u8(reg::STATUS)?))
+    }
+
+    /// Reads STATUS and fails with the matching magnet error, if any.
+    pub fn check_magnet(&mut self) -> Result<(), Error<E>> {
+        shared::check_status(self.read_u8(reg::STATUS)?)
+    }
+
+    /// Reads the automatic gain control value (0..=255 at 5 V, 0..=128 at 3.3 V).
+    pub fn read_agc(&mut self) -> Result<u8, Error<E>> {
+        self.read_u8(reg::AGC)
+    }
+
+    /// Reads the 12-bit CORDIC magnitude.
+    pub fn read_magnitude(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::MAGNITUDE)
+    }
+
+    /// Reads how many times ZPOS/MPOS has been burned (0..=3).
+    pub fn read_zmco(&mut self) -> Result<u8, Error<E>> {
+        Ok(shared::decode_zmco(self.read_u8(reg::ZMCO)?))
+    }
+
+    /// Reads the start position (ZPOS).
+    pub fn read_zero_position(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::ZPOS)
+    }
+
+    /// Writes the start position (ZPOS). Fails with `InvalidConfig` above 4095.
+    pub fn set_zero_position(&mut self, position: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_u12(reg::ZPOS, position)?)
+    }
+
+    /// Reads the stop position (MPOS).
+    pub fn read_max_position(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::MPOS)
+    }
+
+    /// Writes the stop position (MPOS). Fails with `InvalidConfig` above 4095.
+    pub fn set_max_position(&mut self, position: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_u12(reg::MPOS, position)?)
+    }
+
+    /// Reads the maximum angle (MANG).
+    pub fn read_max_angle(&mut self) -> Result<u16, Error<E>> {
+        self.read_u12(reg::MANG)
+    }
+
+    /// Writes the maximum angle (MANG). Fails with `InvalidConfig` above 4095.
+    pub fn set_max_angle(&mut self, angle: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_u12(reg::MANG, angle)?)
+    }
+
+    /// Reads the 14-bit CONF register.
+    pub fn read_conf(&mut self) -> Result<u16, Error<E>> {
+        Ok(shared::decode_conf(self.read_pair(reg::CONF)?))
+    }
+
+    /// Writes the CONF register. Fails with `InvalidConfig` above 14 bits.
+    pub fn write_conf(&mut self, conf: u16) -> Result<(), Error<E>> {
+        self.write(shared::encode_conf(conf)?)
+    }
+
+    /// Reads and decodes the CONF register. Fails with `InvalidConfig` on the reserved OUTS code.
+    pub fn read_config(&mut self) -> Result<Config, Error<E>> {
+        shared::decode_config(self.read_conf()?)
+    }
+
+    /// Writes all CONF settings.
+    pub fn write_config(&mut self, config: Config) -> Result<(), Error<E>> {
+        self.write_conf(config.bits())
+    }
+
+    /// Reads CONF, applies `f` and writes the result back. Returns the written settings.
+    pub fn modify_config<F>(&mut self, f: F) -> Result<Config, Error<E>>
+    where
+        F: FnOnce(Config) -> Config,
+    {
+        let config = f(self.read_config()?);
+        self.write_config(config)?;
+        Ok(config)
+    }
+
+    // Release peripheral
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    // Release peripheral and DIR pin
+    pub fn release_parts(self) -> (I2C, Option<DIR>) {
+        (self.i2c, self.state.dir)
+    }
+
+    // Register access helpers
+    fn check_magnet_if_enabled(&mut self) -> Result<(), Error<E>> {
+        if self.state.check_status {
+            self.check_magnet()?;
+        }
+        Ok(())
+    }
+
+    fn read_u8(&mut self, register: u8) -> Result<u8, Error<E>> {
+        let mut buf = [0u8; 1];
+        self.i2c.write_read(self.state.address, &[register], &mut buf).map_err(Error::Bus)?;
+        Ok(buf[0])
+    }
+
+    fn read_pair(&mut self, register: u8) -> Result<[u8; 2], Error<E>> {
+        let mut buf = [0u8; 2];
+        self.i2c.write_read(self.state.address, &[register], &mut buf).map_err(Error::Bus)?;
+        Ok(buf)
+    }
+
+    fn read_u12(&mut self, register: u8) -> Result<u16, Error<E>> {
+        Ok(shared::decode_u12(self.read_pair(register)?))
+    }
+
+    fn write_u8(&mut self, register: u8, value: u8) -> Result<(), Error<E>> {
+        self.i2c.write(self.state.address, &[register, value]).map_err(Error::Bus)
+    }
+
+    fn write(&mut self, bytes: [u8; 3]) -> Result<(), Error<E>> {
+        self.i2c.write(self.state.address, &bytes).map_err(Error::Bus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::as5600::config::{Hysteresis, PowerMode};
+    use crate::mock::{I2cMock, PinMock, Transaction};
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+
+    const ADDR: u8 = As5600::<I2cMock>::DEFAULT_ADDR;
+    const NACK: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+
+    // Runs `f` on a driver over `script` and checks that the whole script was used.
+    fn with_script<R>(script: &[Transaction], f: impl FnOnce(&mut As5600<I2cMock>) -> R) -> R {
+        let mut encoder = As5600::new(I2cMock::new(script));
+        let result = f(&mut encoder);
+        encoder.release().done();
+        result
+    }
+
+    #[test]
+    fn angle_reads_mask_to_12_bits() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0xFF, 0xFF]),
+            Transaction::write_read(ADDR, &[reg::ANGLE], &[0x08, 0x00]),
+        ];
+        with_script(&script, |encoder| {
+            assert_eq!(encoder.read_raw_angle(), Ok(RawAngle::new(4095)));
+            assert_eq!(encoder.read_angle(), Ok(RawAngle::new(2048)));
+        });
+    }
+
+    #[test]
+    fn degrees_at_range_boundaries() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x00, 0x00]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x0F, 0xFF]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x04, 0x00]),
+        ];
+        with_script(&script, |encoder| {
+            assert_eq!(encoder.read_degrees(), Ok(Degrees(0.0)));
+            assert_eq!(encoder.read_degrees(), Ok(Degrees(4095.0 * 360.0 / 4096.0)));
+            assert_eq!(encoder.read_turns(), Ok(Turns::QUARTER));
+        });
+    }
+
+    #[test]
+    fn software_zero_and_direction() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x00]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x80]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x80]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x00, 0x80]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x00, 0x80]),
+        ];
+        with_script(&script, |encoder| {
+            encoder.set_zero_here().unwrap();
+            assert_eq!(encoder.read_position(), Ok(RawAngle::new(0x080)));
+
+            // Counter-clockwise positive: the same shaft positions read mirrored around the zero.
+            encoder.set_direction(Direction::CounterClockwise);
+            assert_eq!(encoder.read_position(), Ok(RawAngle::new(0xF80)));
+            assert_eq!(encoder.read_degrees(), Ok(Degrees(11.25)));
+            assert_eq!(encoder.read_raw_angle(), Ok(RawAngle::new(0x080)));
+        });
+    }
+
+    #[test]
+    fn dir_pin_switches_direction_in_the_chip() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x00]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x0F, 0x00]),
+        ];
+        let mut encoder = As5600::new(I2cMock::new(&script)).with_dir_pin(PinMock { high: true });
+        encoder.set_zero_here().unwrap();
+        encoder.set_direction(Direction::CounterClockwise);
+        assert_eq!(encoder.direction(), Direction::CounterClockwise);
+        assert!(!encoder.orientation().inverted);
+
+        // The chip now reports the zero position mirrored, at 0xF00.
+        assert_eq!(encoder.read_position(), Ok(RawAngle::ZERO));
+        let (i2c, pin) = encoder.release_parts();
+        assert_eq!(pin, Some(PinMock { high: true }));
+        i2c.done();
+    }
+
+    #[test]
+    fn dir_pin_keeps_a_direction_set_in_software() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x00]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x80]),
+            // With DIR high the chip mirrors the same two shaft positions.
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x0F, 0x00]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x0E, 0x80]),
+        ];
+        let mut encoder = As5600::new(I2cMock::new(&script));
+        encoder.set_zero_here().unwrap();
+        encoder.set_direction(Direction::CounterClockwise);
+        assert_eq!(encoder.read_position(), Ok(RawAngle::new(0xF80)));
+
+        let mut encoder = encoder.with_dir_pin(PinMock { high: false });
+        assert_eq!(encoder.direction(), Direction::CounterClockwise);
+        assert!(!encoder.orientation().inverted);
+        assert_eq!(encoder.read_position(), Ok(RawAngle::ZERO));
+        assert_eq!(encoder.read_position(), Ok(RawAngle::new(0xF80)));
+        let (i2c, pin) = encoder.release_parts();
+        assert_eq!(pin, Some(PinMock { high: true }));
+        i2c.done();
+    }
+
+    #[test]
+    fn status_check_reads_status_before_angle() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::STATUS], &[0x20]),
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0x01, 0x23]),
+            Transaction::write_read(ADDR, &[reg::STATUS], &[0x20]),
+            Transaction::write_read(ADDR, &[reg::ANGLE], &[0x03, 0x21]),
+        ];
+        let mut encoder = As5600::new(I2cMock::new(&script)).with_status_check(true);
+        assert_eq!(encoder.read_raw_angle(), Ok(RawAngle::new(0x123)));
+        assert_eq!(encoder.read_angle(), Ok(RawAngle::new(0x321)));
+        encoder.release().done();
+    }
+
+    #[test]
+    fn magnet_problems_stop_angle_reads() {
+        for (status, error) in [
+            (0x00, Error::MagnetNotDetected),
+            (0x30, Error::MagnetTooWeak),
+            (0x28, Error::MagnetTooStrong),
+        ] {
+            let script = [Transaction::write_read(ADDR, &[reg::STATUS], core::slice::from_ref(&status))];
+            let mut encoder = As5600::new(I2cMock::new(&script)).with_status_check(true);
+            assert_eq!(encoder.read_raw_angle(), Err(error));
+            encoder.release().done();
+        }
+    }
+
+    #[test]
+    fn status_decodes_magnet_bits() {
+        let script = [Transaction::write_read(ADDR, &[reg::STATUS], &[0x38])];
+        with_script(&script, |encoder| {
+            let status = encoder.read_status().unwrap();
+            assert!(status.magnet_detected && status.magnet_too_weak && status.magnet_too_strong);
+        });
+    }
+
+    #[test]
+    fn diagnostic_registers() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::AGC], &[0x80]),
+            Transaction::write_read(ADDR, &[reg::MAGNITUDE], &[0xF5, 0x67]),
+            Transaction::write_read(ADDR, &[reg::ZMCO], &[0xFE]),
+            Transaction::write_read(ADDR, &[reg::STATUS], &[0x20]),
+        ];
+        with_script(&script, |encoder| {
+            assert_eq!(encoder.read_agc(), Ok(0x80));
+            assert_eq!(encoder.read_magnitude(), Ok(0x567));
+            assert_eq!(encoder.read_zmco(), Ok(2));
+            assert_eq!(encoder.check_magnet(), Ok(()));
+        });
+    }
+
+    #[test]
+    fn position_registers_round_trip() {
+        let script = [
+            Transaction::write(ADDR, &[reg::ZPOS, 0x0F, 0xFF]),
+            Transaction::write_read(ADDR, &[reg::ZPOS], &[0xFF, 0xFF]),
+            Transaction::write(ADDR, &[reg::MPOS, 0x08, 0x00]),
+            Transaction::write_read(ADDR, &[reg::MPOS], &[0x08, 0x00]),
+            Transaction::write(ADDR, &[reg::MANG, 0x00, 0x00]),
+            Transaction::write_read(ADDR, &[reg::MANG], &[0x00, 0x00]),
+        ];
+        with_script(&script, |encoder| {
+            assert_eq!(encoder.set_zero_position(4095), Ok(()));
+            assert_eq!(encoder.read_zero_position(), Ok(4095));
+            assert_eq!(encoder.set_max_position(2048), Ok(()));
+            assert_eq!(encoder.read_max_position(), Ok(2048));
+            assert_eq!(encoder.set_max_angle(0), Ok(()));
+            assert_eq!(encoder.read_max_angle(), Ok(0));
+        });
+    }
+
+    #[test]
+    fn out_of_range_values_are_not_written() {
+        with_script(&[], |encoder| {
+            assert_eq!(encoder.set_zero_position(4096), Err(Error::InvalidConfig));
+            assert_eq!(encoder.set_max_position(0xFFFF), Err(Error::InvalidConfig));
+            assert_eq!(encoder.set_max_angle(0x1000), Err(Error::InvalidConfig));
+            assert_eq!(encoder.write_conf(0x4000), Err(Error::InvalidConfig));
+        });
+    }
+
+    #[test]
+    fn conf_register_traffic() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::CONF], &[0xFF, 0xCF]),
+            Transaction::write(ADDR, &[reg::CONF, 0x20, 0x01]),
+            Transaction::write_read(ADDR, &[reg::CONF], &[0x00, 0x30]),
+            Transaction::write_read(ADDR, &[reg::CONF], &[0x00, 0x0C]),
+            Transaction::write(ADDR, &[reg::CONF, 0x00, 0x01]),
+            Transaction::write(ADDR, &[reg::CONF, 0x00, 0x00]),
+        ];
+        with_script(&script, |encoder| {
+            assert_eq!(encoder.read_conf(), Ok(0x3FCF));
+            assert_eq!(encoder.write_conf(0x2001), Ok(()));
+            assert_eq!(encoder.read_config(), Err(Error::InvalidConfig));
+
+            let config = encoder.modify_config(|c| c.hysteresis(Hysteresis::Off).power_mode(PowerMode::Lpm1));
+            assert_eq!(config.map(|c| c.bits()), Ok(0x0001));
+            assert_eq!(encoder.write_config(Config::default()), Ok(()));
+        });
+    }
+
+    #[test]
+    fn bus_errors_propagate() {
+        let script = [
+            Transaction::write_read(ADDR, &[reg::RAW_ANGLE], &[0, 0]).nack(),
+            Transaction::write_read(ADDR, &[reg::STATUS], &[0x20]).with_error(ErrorKind::ArbitrationLoss),
+            Transaction::write(ADDR, &[reg::ZPOS, 0x01, 0x00]).with_error(ErrorKind::Bus),
+            Transaction::write_read(ADDR, &[reg::CONF], &[0, 0]).nack(),
+        ];
+        let mut encoder = As5600::new(I2cMock::new(&script));
+        assert_eq!(encoder.read_raw_angle(), Err(Error::Bus(NACK)));
+        encoder = encoder.with_status_check(true);
+        assert_eq!(encoder.read_degrees(), Err(Error::Bus(ErrorKind::ArbitrationLoss)));
+        assert_eq!(encoder.set_zero_position(0x100), Err(Error::Bus(ErrorKind::Bus)));
+        // A failed read must not be followed by the write.
+        assert_eq!(encoder.modify_config(|c| c), Err(Error::Bus(NACK)));
+        encoder.release().done();
+    }
+}
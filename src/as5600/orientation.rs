@@ -0,0 +1,92 @@
+// Software zero offset and direction.
+//
+// Applied on top of the sensor angle, so "this is zero" and "clockwise is
+// positive" can be set at runtime without burning ZPOS or rewiring DIR. The
+// transform works in `Turns`, so it is exact for raw angles and safe in an ISR.
+
+// Imports
+use core::convert::Infallible;
+use embedded_hal::digital::{ErrorType, OutputPin};
+
+use crate::angle::Turns;
+
+/// Which way of turning, seen from above the chip, increases the angle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum Direction {
+    /// The AS5600 default, with DIR low.
+    #[default]
+    Clockwise,
+    /// With DIR high, or inverted in software.
+    CounterClockwise,
+}
+
+impl Direction {
+    /// The opposite direction.
+    pub fn reversed(self) -> Self {
+        match self {
+            Direction::Clockwise => Direction::CounterClockwise,
+            Direction::CounterClockwise => Direction::Clockwise,
+        }
+    }
+}
+
+/// Zero offset and inversion applied to the sensor angle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub struct Orientation {
+    /// Sensor angle that reads as zero.
+    pub zero: Turns,
+    /// Negate the angle around `zero`.
+    pub inverted: bool,
+}
+
+impl Orientation {
+    /// Leaves the sensor angle unchanged.
+    pub const IDENTITY: Self = Self { zero: Turns::ZERO, inverted: false };
+
+    /// Maps a sensor angle to the user angle.
+    pub fn apply(&self, sensor: Turns) -> Turns {
+        let angle = sensor - self.zero;
+        if self.inverted { -angle } else { angle }
+    }
+
+    /// Maps a user angle back to the sensor angle.
+    pub fn unapply(&self, angle: Turns) -> Turns {
+        let angle = if self.inverted { -angle } else { angle };
+        angle + self.zero
+    }
+}
+
+/// DIR pin type of a driver without a DIR pin. It has no values.
+pub enum NoDirPin {}
+
+impl ErrorType for NoDirPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoDirPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        match *self {}
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_and_inversion_round_trip() {
+        let orientation = Orientation { zero: Turns::from_bits(0x1000), inverted: true };
+        assert_eq!(orientation.apply(Turns::from_bits(0x1000)), Turns::ZERO);
+        assert_eq!(orientation.apply(Turns::from_bits(0x1100)), Turns::from_bits(0xFF00));
+        assert_eq!(orientation.apply(Turns::from_bits(0x0F00)), Turns::from_bits(0x0100));
+        for bits in [0, 1, 0x7FFF, 0x8000, 0xFFFF] {
+            let angle = Turns::from_bits(bits);
+            assert_eq!(orientation.unapply(orientation.apply(angle)), angle);
+        }
+        assert_eq!(Orientation::IDENTITY.apply(Turns::HALF), Turns::HALF);
+    }
+}
@@ -0,0 +1,348 @@
+// One-time programmable (OTP) burn support.
+//
+// Burning is permanent, so a burn is split in two steps: `prepare_*` checks the
+// ZMCO counter and the magnet, writes the values and hands back a token. Only the
+// token can issue the BURN command, and it is consumed by doing so.
+
+// Imports
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+use super::{reg, As5600, Config, Error, NoDirPin};
+
+// BURN register commands
+const BURN_ANGLE: u8 = 0x80;
+const BURN_SETTING: u8 = 0x40;
+const LOAD_OTP: [u8; 3] = [0x01, 0x11, 0x10];
+
+/// ZPOS/MPOS can be burned at most this many times.
+pub const MAX_ANGLE_BURNS: u8 = 3;
+
+/// Errors from preparing or performing an OTP burn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BurnError<E> {
+    /// Bus error, invalid value or no magnet detected.
+    Sensor(Error<E>),
+    /// ZMCO shows no burns left for this command.
+    BurnLimitReached,
+    /// The registers did not hold the expected values afterwards.
+    VerifyFailed,
+}
+
+impl<E> From<Error<E>> for BurnError<E> {
+    fn from(error: Error<E>) -> Self {
+        BurnError::Sensor(error)
+    }
+}
+
+/// Token for a pending BURN_ANGLE of ZPOS/MPOS. Created by `As5600::prepare_burn_angle`.
+#[must_use = "dropping the token cancels the burn"]
+pub struct BurnAngle<'a, I2C, DIR = NoDirPin> {
+    sensor: &'a mut As5600<I2C, DIR>,
+    zero_position: u16,
+    max_position: u16,
+}
+
+/// Token for a pending BURN_SETTING of MANG/CONF. Created by `As5600::prepare_burn_setting`.
+#[must_use = "dropping the token cancels the burn"]
+pub struct BurnSetting<'a, I2C, DIR = NoDirPin> {
+    sensor: &'a mut As5600<I2C, DIR>,
+    max_angle: u16,
+    conf: u16,
+}
+
+impl<I2C, DIR, E> As5600<I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Checks that ZPOS/MPOS may be burned, writes them and returns a burn token.
+    ///
+    /// Refuses if ZMCO has reached `MAX_ANGLE_BURNS`, no magnet is detected or
+    /// a position is above 4095.
+    pub fn prepare_burn_angle(
+        &mut self,
+        zero_position: u16,
+        max_position: u16,
+    ) -> Result<BurnAngle<'_, I2C, DIR>, BurnError<E>> {
+        if self.read_zmco()? >= MAX_ANGLE_BURNS {
+            return Err(BurnError::BurnLimitReached);
+        }
+        self.check_magnet_detected()?;
+
+        self.set_zero_position(zero_position)?;
+        self.set_max_position(max_position)?;
+        if self.read_zero_position()? != zero_position || self.read_max_position()? != max_position {
+            return Err(BurnError::VerifyFailed);
+        }
+
+        Ok(BurnAngle { sensor: self, zero_position, max_position })
+    }
+
+    /// Checks that MANG/CONF may be burned, writes them and returns a burn token.
+    ///
+    /// BURN_SETTING only works once and only while ZPOS/MPOS have never been
+    /// burned, so this refuses unless ZMCO is 0 and a magnet is detected.
+    pub fn prepare_burn_setting(
+        &mut self,
+        max_angle: u16,
+        config: Config,
+    ) -> Result<BurnSetting<'_, I2C, DIR>, BurnError<E>> {
+        let conf = config.bits();
+
+        if self.read_zmco()? != 0 {
+            return Err(BurnError::BurnLimitReached);
+        }
+        self.check_magnet_detected()?;
+
+        self.set_max_angle(max_angle)?;
+        self.write_conf(conf)?;
+        if self.read_max_angle()? != max_angle || self.read_conf()? != conf {
+            return Err(BurnError::VerifyFailed);
+        }
+
+        Ok(BurnSetting { sensor: self, max_angle, conf })
+    }
+
+    // Burn helpers
+    // Only MD matters for burning, AGC saturation does not affect the OTP.
+    fn check_magnet_detected(&mut self) -> Result<(), Error<E>> {
+        if self.read_status()?.magnet_detected {
+            Ok(())
+        } else {
+            Err(Error::MagnetNotDetected)
+        }
+    }
+
+    fn burn<D: DelayNs>(&mut self, command: u8, delay: &mut D) -> Result<(), Error<E>> {
+        self.write_u8(reg::BURN, command)?;
+        delay.delay_ms(1);
+
+        // Reload the OTP content into the registers so it can be read back.
+        for cmd in LOAD_OTP {
+            self.write_u8(reg::BURN, cmd)?;
+        }
+        Ok(())
+    }
+}
+
+impl<I2C, DIR, E> BurnAngle<'_, I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    /// ZPOS that will be burned.
+    pub fn zero_position(&self) -> u16 {
+        self.zero_position
+    }
+
+    /// MPOS that will be burned.
+    pub fn max_position(&self) -> u16 {
+        self.max_position
+    }
+
+    /// Permanently burns ZPOS/MPOS, reloads the OTP and verifies the result.
+    pub fn burn<D: DelayNs>(self, delay: &mut D) -> Result<(), BurnError<E>> {
+        self.sensor.burn(BURN_ANGLE, delay)?;
+
+        if self.sensor.read_zero_position()? != self.zero_position
+            || self.sensor.read_max_position()? != self.max_position
+        {
+            return Err(BurnError::VerifyFailed);
+        }
+        Ok(())
+    }
+}
+
+impl<I2C, DIR, E> BurnSetting<'_, I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    /// MANG that will be burned.
+    pub fn max_angle(&self) -> u16 {
+        self.max_angle
+    }
+
+    /// CONF settings that will be burned.
+    pub fn config(&self) -> Config {
+        Config::from_bits(self.conf)
+    }
+
+    /// Permanently burns MANG/CONF, reloads the OTP and verifies the result.
+    pub fn burn<D: DelayNs>(self, delay: &mut D) -> Result<(), BurnError<E>> {
+        self.sensor.burn(BURN_SETTING, delay)?;
+
+        if self.sensor.read_max_angle()? != self.max_angle || self.sensor.read_conf()? != self.conf {
+            return Err(BurnError::VerifyFailed);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::as5600::config::PowerMode;
+    use embedded_hal::i2c::{ErrorKind, ErrorType, Operation};
+
+    // Register-level fake of the AS5600 with OTP behaviour.
+    struct FakeAs5600 {
+        regs: [u8; 256],
+        otp: [u8; 256],
+        pointer: u8,
+        angle_burns: u8,
+        setting_burns: u8,
+    }
+
+    impl FakeAs5600 {
+        fn new(zmco: u8, magnet: bool) -> Self {
+            let mut regs = [0u8; 256];
+            regs[reg::ZMCO as usize] = zmco;
+            regs[reg::STATUS as usize] = if magnet { 1 << 5 } else { 0 };
+            Self { regs, otp: regs, pointer: 0, angle_burns: 0, setting_burns: 0 }
+        }
+
+        fn reg16(&self, register: u8) -> u16 {
+            u16::from_be_bytes([self.regs[register as usize], self.regs[register as usize + 1]])
+        }
+
+        fn copy_to_otp(&mut self, first: u8, last: u8) {
+            for r in first..=last {
+                self.otp[r as usize] = self.regs[r as usize];
+            }
+        }
+
+        fn handle_write(&mut self, bytes: &[u8]) {
+            self.pointer = bytes[0];
+            for (i, &byte) in bytes[1..].iter().enumerate() {
+                let register = self.pointer.wrapping_add(i as u8);
+                if register == reg::BURN {
+                    self.handle_burn(byte);
+                } else {
+                    self.regs[register as usize] = byte;
+                }
+            }
+        }
+
+        fn handle_burn(&mut self, command: u8) {
+            match command {
+                BURN_ANGLE => {
+                    self.angle_burns += 1;
+                    self.copy_to_otp(reg::ZPOS, reg::MPOS + 1);
+                    self.otp[reg::ZMCO as usize] += 1;
+                }
+                BURN_SETTING => {
+                    self.setting_burns += 1;
+                    self.copy_to_otp(reg::MANG, reg::CONF + 1);
+                }
+                0x10 => self.regs[..=reg::CONF as usize + 1]
+                    .copy_from_slice(&self.otp[..=reg::CONF as usize + 1]),
+                _ => {}
+            }
+        }
+    }
+
+    impl ErrorType for FakeAs5600 {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for FakeAs5600 {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+            assert_eq!(address, As5600::<Self>::DEFAULT_ADDR);
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => self.handle_write(bytes),
+                    Operation::Read(buf) => {
+                        for (i, byte) in buf.iter_mut().enumerate() {
+                            *byte = self.regs[self.pointer.wrapping_add(i as u8) as usize];
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn burn_angle_writes_burns_and_verifies() {
+        let mut sensor = As5600::new(FakeAs5600::new(0, true));
+        let token = sensor.prepare_burn_angle(0x123, 0xABC).unwrap();
+        assert_eq!((token.zero_position(), token.max_position()), (0x123, 0xABC));
+        token.burn(&mut NoDelay).unwrap();
+
+        assert_eq!(sensor.read_zmco(), Ok(1));
+        let fake = sensor.release();
+        assert_eq!(fake.angle_burns, 1);
+        assert_eq!(fake.reg16(reg::ZPOS), 0x123);
+        assert_eq!(fake.reg16(reg::MPOS), 0xABC);
+    }
+
+    #[test]
+    fn burn_angle_refused_when_counter_exhausted() {
+        let mut sensor = As5600::new(FakeAs5600::new(MAX_ANGLE_BURNS, true));
+        assert!(matches!(sensor.prepare_burn_angle(1, 2), Err(BurnError::BurnLimitReached)));
+        assert_eq!(sensor.release().reg16(reg::ZPOS), 0);
+    }
+
+    #[test]
+    fn burn_angle_refused_without_magnet() {
+        let mut sensor = As5600::new(FakeAs5600::new(0, false));
+        assert!(matches!(
+            sensor.prepare_burn_angle(1, 2),
+            Err(BurnError::Sensor(Error::MagnetNotDetected))
+        ));
+        assert_eq!(sensor.release().reg16(reg::ZPOS), 0);
+    }
+
+    #[test]
+    fn burn_angle_refuses_out_of_range_position() {
+        let mut sensor = As5600::new(FakeAs5600::new(0, true));
+        assert!(matches!(
+            sensor.prepare_burn_angle(0x1000, 2),
+            Err(BurnError::Sensor(Error::InvalidConfig))
+        ));
+    }
+
+    #[test]
+    fn dropped_token_does_not_burn() {
+        let mut sensor = As5600::new(FakeAs5600::new(0, true));
+        drop(sensor.prepare_burn_angle(5, 6).unwrap());
+        assert_eq!(sensor.release().angle_burns, 0);
+    }
+
+    #[test]
+    fn burn_setting_requires_unburned_angles() {
+        let mut sensor = As5600::new(FakeAs5600::new(1, true));
+        assert!(matches!(
+            sensor.prepare_burn_setting(0x800, Config::default()),
+            Err(BurnError::BurnLimitReached)
+        ));
+    }
+
+    #[test]
+    fn burn_setting_writes_burns_and_verifies() {
+        let config = Config::default().power_mode(PowerMode::Lpm3).watchdog(true);
+        let mut sensor = As5600::new(FakeAs5600::new(0, true));
+        let token = sensor.prepare_burn_setting(0x800, config).unwrap();
+        assert_eq!(token.config(), config);
+        token.burn(&mut NoDelay).unwrap();
+
+        assert_eq!(sensor.read_config(), Ok(config));
+        let fake = sensor.release();
+        assert_eq!(fake.setting_burns, 1);
+        assert_eq!(fake.reg16(reg::MANG), 0x800);
+    }
+
+    #[test]
+    fn burn_reports_verify_failure() {
+        let mut sensor = As5600::new(FakeAs5600::new(0, true));
+        let token = sensor.prepare_burn_angle(0x100, 0x200).unwrap();
+        // Corrupt the shadow register so the OTP reload brings back a wrong value.
+        token.sensor.i2c.regs[reg::ZPOS as usize + 1] = 0x55;
+        assert_eq!(token.burn(&mut NoDelay), Err(BurnError::VerifyFailed));
+    }
+}
@@ -0,0 +1,276 @@
+// Angle from the OUT pin, without I2C.
+//
+// With OUTS set to an analog stage, OUT is a voltage ratiometric to VDD, read here
+// with ADC1. With OUTS set to PWM, OUT is a frame of 4351 PWM clock periods: 128
+// high (header), the angle in 0..=4095 periods (high), then 128 low (footer). The
+// timer in PWM input mode captures the period and high time; their ratio gives the
+// angle whatever the PWM frequency and the AS5600's oscillator tolerance.
+//
+// Both readers return the same `RawAngle` as the I2C driver and implement
+// `RotaryEncoder`.
+
+// Imports
+use core::convert::Infallible;
+use stm32f4xx_hal::adc::{config::SampleTime, Adc};
+use stm32f4xx_hal::hal_02::adc::Channel;
+use stm32f4xx_hal::pac::{self, ADC1};
+use stm32f4xx_hal::timer::PwmInput;
+
+use crate::angle::{Degrees, RawAngle, Turns, COUNTS_PER_TURN};
+use crate::encoder::RotaryEncoder;
+
+/// PWM clock periods in one frame.
+pub const PWM_FRAME_PERIODS: u32 = 4351;
+/// PWM clock periods in the header (high) and in the footer (low).
+pub const PWM_HEADER_PERIODS: u32 = 128;
+// Capture jitter allowed beyond the header and footer, in PWM clock periods.
+const PWM_TOLERANCE_PERIODS: u32 = 4;
+
+/// Output voltage range of the analog stage, matching OUTS in CONF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum AnalogRange {
+    /// 0% to 100% of VDD (`OutputStage::AnalogFull`).
+    Full,
+    /// 10% to 90% of VDD (`OutputStage::AnalogReduced`).
+    Reduced,
+}
+
+/// Converts an ADC sample of OUT into an angle.
+///
+/// `full_scale` is the sample at VDD, 4095 for the 12-bit ADC. Samples outside
+/// the reduced range are clamped to its ends.
+pub fn decode_analog(sample: u16, full_scale: u16, range: AnalogRange) -> RawAngle {
+    let full_scale = full_scale as u32;
+    // Range ends in samples, times 10 to keep the 10% ends exact.
+    let (low, high) = match range {
+        AnalogRange::Full => (0, full_scale * 10),
+        AnalogRange::Reduced => (full_scale, full_scale * 9),
+    };
+    let sample = (sample as u32 * 10).clamp(low, high);
+    let max = COUNTS_PER_TURN as u32 - 1;
+    let counts = ((sample - low) * max + (high - low) / 2) / (high - low);
+    RawAngle::new(counts as u16)
+}
+
+/// Errors from decoding the PWM output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum PwmError {
+    /// No complete PWM period has been captured: OUT is stuck or not in PWM mode.
+    NoSignal,
+    /// The high time lies outside the header/footer frame, so the signal is not an AS5600 frame.
+    InvalidFrame,
+}
+
+/// Converts a captured PWM period and high time, both in timer clocks, into an angle.
+pub fn decode_pwm(high_clocks: u32, period_clocks: u32) -> Result<RawAngle, PwmError> {
+    if period_clocks == 0 || high_clocks == 0 || high_clocks >= period_clocks {
+        return Err(PwmError::NoSignal);
+    }
+    let periods = (high_clocks as u64 * PWM_FRAME_PERIODS as u64 + period_clocks as u64 / 2) / period_clocks as u64;
+    let periods = periods as u32;
+    let first = PWM_HEADER_PERIODS;
+    let last = PWM_FRAME_PERIODS - PWM_HEADER_PERIODS;
+    if periods + PWM_TOLERANCE_PERIODS < first || periods > last + PWM_TOLERANCE_PERIODS {
+        return Err(PwmError::InvalidFrame);
+    }
+    Ok(RawAngle::new((periods.clamp(first, last) - first) as u16))
+}
+
+/// AS5600 analog output on an ADC1 channel, e.g. PA0 (A0).
+pub struct As5600Analog<PIN> {
+    adc: Adc<ADC1>,
+    pin: PIN,
+    range: AnalogRange,
+}
+
+impl<PIN> As5600Analog<PIN>
+where
+    PIN: Channel<ADC1, ID = u8>,
+{
+    // Constructor, for the ADC at its default 12-bit resolution
+    pub fn new(adc: Adc<ADC1>, pin: PIN, range: AnalogRange) -> Self {
+        Self { adc, pin, range }
+    }
+
+    /// Samples OUT and converts it to an angle.
+    pub fn read_raw_angle(&mut self) -> RawAngle {
+        let sample = self.adc.convert(&self.pin, SampleTime::Cycles_480);
+        decode_analog(sample, 4095, self.range)
+    }
+
+    /// Samples OUT as a fixed-point fraction of a turn.
+    pub fn read_turns(&mut self) -> Turns {
+        self.read_raw_angle().to_turns()
+    }
+
+    /// Samples OUT in degrees (0.0 - 360.0).
+    pub fn read_degrees(&mut self) -> Degrees {
+        self.read_raw_angle().to_degrees()
+    }
+
+    // Release peripherals
+    pub fn release(self) -> (Adc<ADC1>, PIN) {
+        (self.adc, self.pin)
+    }
+}
+
+impl<PIN> RotaryEncoder for As5600Analog<PIN>
+where
+    PIN: Channel<ADC1, ID = u8>,
+{
+    type Error = Infallible;
+
+    fn counts_per_revolution(&self) -> u32 {
+        COUNTS_PER_TURN as u32
+    }
+
+    fn position(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.read_raw_angle().counts() as u32)
+    }
+}
+
+/// A timer in PWM input mode, as set up by the HAL's `Timer::pwm_input`.
+pub trait PwmCapture {
+    /// Last captured period, in timer clocks.
+    fn period_clocks(&self) -> u32;
+    /// Last captured high time, in timer clocks.
+    fn high_clocks(&self) -> u32;
+}
+
+macro_rules! pwm_capture {
+    ($($TIM:ty),*) => {
+        $(
+            impl PwmCapture for PwmInput<$TIM> {
+                fn period_clocks(&self) -> u32 {
+                    self.get_period_clocks().into()
+                }
+
+                fn high_clocks(&self) -> u32 {
+                    self.get_duty_cycle_clocks().into()
+                }
+            }
+        )*
+    };
+}
+
+// Timers with PWM input on all supported chips.
+pwm_capture!(pac::TIM1, pac::TIM2, pac::TIM3, pac::TIM4, pac::TIM5, pac::TIM9);
+
+/// AS5600 PWM output on a timer channel 1 pin in PWM input mode.
+///
+/// Configure the timer with `pwm_input` at roughly the PWM frequency set in CONF
+/// (115 to 920 Hz). Reads return the last complete frame.
+pub struct As5600Pwm<CAP> {
+    capture: CAP,
+}
+
+impl<CAP: PwmCapture> As5600Pwm<CAP> {
+    // Constructor
+    pub fn new(capture: CAP) -> Self {
+        Self { capture }
+    }
+
+    /// Decodes the last captured frame.
+    pub fn read_raw_angle(&mut self) -> Result<RawAngle, PwmError> {
+        decode_pwm(self.capture.high_clocks(), self.capture.period_clocks())
+    }
+
+    /// Decodes the last captured frame as a fixed-point fraction of a turn.
+    pub fn read_turns(&mut self) -> Result<Turns, PwmError> {
+        Ok(self.read_raw_angle()?.to_turns())
+    }
+
+    /// Decodes the last captured frame in degrees (0.0 - 360.0).
+    pub fn read_degrees(&mut self) -> Result<Degrees, PwmError> {
+        Ok(self.read_raw_angle()?.to_degrees())
+    }
+
+    // Release timer
+    pub fn release(self) -> CAP {
+        self.capture
+    }
+}
+
+impl<CAP: PwmCapture> RotaryEncoder for As5600Pwm<CAP> {
+    type Error = PwmError;
+
+    fn counts_per_revolution(&self) -> u32 {
+        COUNTS_PER_TURN as u32
+    }
+
+    fn position(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.read_raw_angle()?.counts() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured timer state for a frame of `angle` at `clocks_per_period` timer clocks per PWM period.
+    struct Frame {
+        high: u32,
+        period: u32,
+    }
+
+    impl Frame {
+        fn new(angle: u32, clocks_per_period: u32) -> Self {
+            Self {
+                high: (PWM_HEADER_PERIODS + angle) * clocks_per_period,
+                period: PWM_FRAME_PERIODS * clocks_per_period,
+            }
+        }
+    }
+
+    impl PwmCapture for Frame {
+        fn period_clocks(&self) -> u32 {
+            self.period
+        }
+
+        fn high_clocks(&self) -> u32 {
+            self.high
+        }
+    }
+
+    #[test]
+    fn analog_ranges_map_to_full_turn() {
+        assert_eq!(decode_analog(0, 4095, AnalogRange::Full), RawAngle::new(0));
+        assert_eq!(decode_analog(4095, 4095, AnalogRange::Full), RawAngle::new(4095));
+        assert_eq!(decode_analog(2048, 4095, AnalogRange::Full), RawAngle::new(2048));
+
+        // 10% and 90% of VDD are the ends of the reduced range, beyond them is clamped.
+        assert_eq!(decode_analog(410, 4095, AnalogRange::Reduced), RawAngle::new(1));
+        assert_eq!(decode_analog(100, 4095, AnalogRange::Reduced), RawAngle::new(0));
+        assert_eq!(decode_analog(4000, 4095, AnalogRange::Reduced), RawAngle::new(4095));
+        assert_eq!(decode_analog(2048, 4095, AnalogRange::Reduced), RawAngle::new(2048));
+    }
+
+    #[test]
+    fn pwm_frame_decodes_at_any_frequency() {
+        for angle in [0, 1, 2048, 4094, 4095] {
+            for clocks_per_period in [3, 20, 167] {
+                let mut encoder = As5600Pwm::new(Frame::new(angle, clocks_per_period));
+                assert_eq!(encoder.read_raw_angle(), Ok(RawAngle::new(angle as u16)));
+            }
+        }
+        // A 5% fast oscillator stretches nothing, the ratio stays the same.
+        let frame = Frame::new(1000, 20);
+        assert_eq!(decode_pwm(frame.high * 105 / 100, frame.period * 105 / 100), Ok(RawAngle::new(1000)));
+    }
+
+    #[test]
+    fn pwm_rejects_missing_and_foreign_signals() {
+        assert_eq!(decode_pwm(0, 0), Err(PwmError::NoSignal));
+        assert_eq!(decode_pwm(500, 500), Err(PwmError::NoSignal));
+        // A 50% duty square wave is inside the frame; 1% and 99% duty lie in the header and footer.
+        assert_eq!(decode_pwm(500, 1000), Ok(RawAngle::new(2048)));
+        assert_eq!(decode_pwm(10, 1000), Err(PwmError::InvalidFrame));
+        assert_eq!(decode_pwm(990, 1000), Err(PwmError::InvalidFrame));
+        // Jitter just short of the header still reads as angle 0, up to the tolerance.
+        assert_eq!(decode_pwm(126, 4351), Ok(RawAngle::ZERO));
+        assert_eq!(decode_pwm(124, 4351), Ok(RawAngle::ZERO));
+        assert_eq!(decode_pwm(123, 4351), Err(PwmError::InvalidFrame));
+        // Just past the header is the first step of the angle.
+        assert_eq!(decode_pwm(129, 4351), Ok(RawAngle::new(1)));
+    }
+}
@@ -0,0 +1,132 @@
+// Bus-independent half of the AS5600 drivers, shared by `As5600` and `As5600Async`.
+//
+// Register decoding, range checks, the status check and the software zero and
+// direction live here as plain functions, so the two front-ends only differ in
+// how they move bytes over the bus.
+
+// Imports
+use core::convert::Infallible;
+use embedded_hal::digital::OutputPin;
+
+use super::{reg, Config, Direction, Error, Orientation, Status};
+use crate::angle::{RawAngle, Turns};
+
+/// Fixed 7-bit I2C address of the AS5600.
+pub const DEFAULT_ADDR: u8 = 0x36;
+
+/// Driver settings and the optional DIR pin.
+pub struct State<DIR> {
+    pub address: u8,
+    pub check_status: bool,
+    pub orientation: Orientation,
+    pub direction: Direction,
+    pub dir: Option<DIR>,
+}
+
+impl<DIR> State<DIR> {
+    // Constructor
+    pub fn new() -> Self {
+        Self {
+            address: DEFAULT_ADDR,
+            check_status: false,
+            orientation: Orientation::IDENTITY,
+            direction: Direction::Clockwise,
+            dir: None,
+        }
+    }
+
+    /// Takes `pin` as the DIR pin and drives it to the current direction.
+    /// A software inversion is handed to the chip, so positions read the same.
+    pub fn with_dir_pin<P>(self, mut pin: P) -> State<P>
+    where
+        P: OutputPin<Error = Infallible>,
+    {
+        let Ok(()) = pin.set_state((self.direction == Direction::CounterClockwise).into());
+        let mut orientation = self.orientation;
+        if orientation.inverted {
+            // The chip now mirrors its angle, so the zero moves to the mirrored position.
+            orientation = Orientation { zero: -orientation.zero, inverted: false };
+        }
+        State {
+            address: self.address,
+            check_status: self.check_status,
+            orientation,
+            direction: self.direction,
+            dir: Some(pin),
+        }
+    }
+
+    /// Applies the software zero and direction to a RAW ANGLE reading.
+    pub fn position(&self, raw: RawAngle) -> Turns {
+        self.orientation.apply(raw.to_turns())
+    }
+}
+
+impl<DIR> State<DIR>
+where
+    DIR: OutputPin<Error = Infallible>,
+{
+    /// Drives the DIR pin if there is one, otherwise inverts in software.
+    pub fn set_direction(&mut self, direction: Direction) {
+        if let Some(pin) = self.dir.as_mut() {
+            if direction != self.direction {
+                // The chip mirrors its angle, so the zero moves to the mirrored position.
+                self.orientation.zero = -self.orientation.zero;
+            }
+            let Ok(()) = pin.set_state((direction == Direction::CounterClockwise).into());
+        } else {
+            self.orientation.inverted = direction == Direction::CounterClockwise;
+        }
+        self.direction = direction;
+    }
+}
+
+/// Maps a STATUS byte to the matching magnet error, if any.
+pub fn check_status<E>(bits: u8) -> Result<(), Error<E>> {
+    Status::from_bits(bits).check()
+}
+
+/// Decodes a big-endian 12-bit register pair.
+pub fn decode_u12(buf: [u8; 2]) -> u16 {
+    u16::from_be_bytes(buf) & 0x0FFF
+}
+
+/// Decodes the 14-bit CONF register pair.
+pub fn decode_conf(buf: [u8; 2]) -> u16 {
+    u16::from_be_bytes(buf) & 0x3FFF
+}
+
+/// Decodes the 2-bit ZMCO burn counter.
+pub fn decode_zmco(bits: u8) -> u8 {
+    bits & 0x03
+}
+
+/// Decodes CONF into settings. Fails with `InvalidConfig` on the reserved OUTS code.
+pub fn decode_config<E>(conf: u16) -> Result<Config, Error<E>> {
+    if (conf >> 4) & 0b11 == 0b11 {
+        return Err(Error::InvalidConfig);
+    }
+    Ok(Config::from_bits(conf))
+}
+
+/// Encodes a write of a 12-bit position or angle register. Fails with `InvalidConfig` above 4095.
+pub fn encode_u12<E>(register: u8, value: u16) -> Result<[u8; 3], Error<E>> {
+    if value > 0x0FFF {
+        return Err(Error::InvalidConfig);
+    }
+    Ok(encode_u16(register, value))
+}
+
+/// Encodes a write of CONF. Fails with `InvalidConfig` above 14 bits.
+pub fn encode_conf<E>(conf: u16) -> Result<[u8; 3], Error<E>> {
+    if conf > 0x3FFF {
+        return Err(Error::InvalidConfig);
+    }
+    Ok(encode_u16(reg::CONF, conf))
+}
+
+// Register address followed by the big-endian value
+fn encode_u16(register: u8, value: u16) -> [u8; 3] {
+    let [msb, lsb] = value.to_be_bytes();
+    [register, msb, lsb]
+}
@@ -0,0 +1,212 @@
+// NUCLEO-64 board support: NUCLEO-F401RE, -F411RE and -F446RE, and F407 boards
+// wired the same way.
+//
+// Every example used to take the peripherals, split the GPIO ports and set up
+// the same pins by hand, and a wrong pin only shows as a dead LED or a silent
+// serial port. `Board` does that once, with the pins the board is wired for:
+//
+//   LD2  green user LED     PA5 (D13), active high
+//   B1   blue user button   PC13, pulls low when pressed
+//   VCP  ST-LINK COM port   USART2, PA2 (TX, D1) / PA3 (RX, D0), 115200 8N1
+//   I2C  Arduino header     I2C1, PB8 (SCL, D15) / PB9 (SDA, D14), 100 kHz
+//   A0   analog input       PA0, read with ADC1
+//
+// The other Arduino header pins are handed out unconfigured in `header`, with the
+// timers, DMA controllers and core peripherals the examples use. Morpho-only pins
+// are not; examples that need them set up the peripherals themselves.
+
+// Imports
+use stm32f4xx_hal::{
+    adc::{Adc, config::AdcConfig},
+    gpio::{Analog, Debugger, Output, PA0, PA1, PA4, PA5, PA6, PA7, PA8, PA9, PA10, PB0, PB3, PB4, PB5, PB6, PB10, PC0, PC1, PC7, PC13},
+    i2c::I2c,
+    pac::{self, ADC1, DMA1, DMA2, EXTI, I2C1, SYSCFG, TIM1, TIM2, TIM3, TIM4, TIM5, TIM9, TIM10, TIM11, USART2},
+    prelude::*,
+    rcc::{Clocks, SYSCLK_MAX},
+    serial::{Serial, config::Config},
+};
+
+use crate::button::{ActiveLevel, Button};
+use crate::timebase::Timebase;
+
+/// Green user LED LD2.
+pub type Ld2 = PA5<Output>;
+
+/// Blue user button B1, debounced.
+pub type B1 = Button<PC13>;
+
+/// Serial port to the ST-LINK, which shows up as a COM port on the host.
+pub type Vcp = Serial<USART2>;
+
+/// I2C on the Arduino header, D15 (SCL) and D14 (SDA).
+pub type ArduinoI2c = I2c<I2C1>;
+
+/// Core clock setup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum ClockProfile {
+    /// 16 MHz internal oscillator (HSI), as out of reset.
+    #[default]
+    Hsi16,
+    /// The chip's maximum from the HSI through the PLL: 84 MHz on the F401, 100 MHz
+    /// on the F411, 168 MHz on the F407 and 180 MHz on the F446.
+    HsiMax,
+    /// The chip's maximum from an 8 MHz clock on the HSE input. More accurate than the HSI.
+    ///
+    /// On the Nucleo boards that is the ST-LINK's clock (MCO, bypass mode), which
+    /// needs the default solder bridges; on the F407 an 8 MHz crystal.
+    HseMax,
+}
+
+impl ClockProfile {
+    /// Sets up the clocks for this profile.
+    pub fn freeze(self, rcc: pac::RCC) -> Clocks {
+        let cfgr = rcc.constrain().cfgr;
+        match self {
+            ClockProfile::Hsi16 => cfgr.freeze(),
+            ClockProfile::HsiMax => cfgr.sysclk(SYSCLK_MAX.Hz()).freeze(),
+            #[cfg(not(feature = "stm32f407"))]
+            ClockProfile::HseMax => cfgr.use_hse(8.MHz()).bypass_hse_oscillator().sysclk(SYSCLK_MAX.Hz()).freeze(),
+            #[cfg(feature = "stm32f407")]
+            ClockProfile::HseMax => cfgr.use_hse(8.MHz()).sysclk(SYSCLK_MAX.Hz()).freeze(),
+        }
+    }
+}
+
+/// Arduino header pins not used by the board resources, unconfigured.
+pub struct Header {
+    pub d2: PA10,
+    pub d3: PB3<Debugger>,
+    pub d4: PB5,
+    pub d5: PB4<Debugger>,
+    pub d6: PB10,
+    pub d7: PA8,
+    pub d8: PA9,
+    pub d9: PC7,
+    pub d10: PB6,
+    pub d11: PA7,
+    pub d12: PA6,
+    pub a1: PA1,
+    pub a2: PA4,
+    pub a3: PB0,
+    pub a4: PC1,
+    pub a5: PC0,
+}
+
+/// NUCLEO-64 board, set up and ready to use.
+#[allow(non_snake_case)]
+pub struct Board {
+    pub clocks: Clocks,
+    pub timebase: Timebase,
+
+    // Board resources
+    pub ld2: Ld2,
+    pub b1: B1,
+    pub vcp: Vcp,
+    pub i2c: ArduinoI2c,
+    pub a0: PA0<Analog>,
+    pub adc: Adc<ADC1>,
+    pub header: Header,
+
+    // Peripherals left for the application
+    pub TIM1: TIM1,
+    pub TIM2: TIM2,
+    pub TIM3: TIM3,
+    pub TIM4: TIM4,
+    pub TIM5: TIM5,
+    pub TIM9: TIM9,
+    pub TIM10: TIM10,
+    pub TIM11: TIM11,
+    pub DMA1: DMA1,
+    pub DMA2: DMA2,
+    pub EXTI: EXTI,
+    pub SYSCFG: SYSCFG,
+    pub NVIC: cortex_m::peripheral::NVIC,
+    pub SCB: cortex_m::peripheral::SCB,
+    pub SYST: cortex_m::peripheral::SYST,
+}
+
+impl Board {
+    /// Takes the peripherals and sets up the board, `None` if they were taken already.
+    pub fn take(profile: ClockProfile) -> Option<Self> {
+        // Core peripherals first: if they are taken already, the device peripherals stay untouched.
+        let cp = cortex_m::Peripherals::take()?;
+        let dp = pac::Peripherals::take()?;
+        Some(Self::new(dp, cp, profile))
+    }
+
+    /// Sets up the board from peripherals taken elsewhere, e.g. RTIC's `init` context.
+    pub fn new(dp: pac::Peripherals, cp: cortex_m::Peripherals, profile: ClockProfile) -> Self {
+        let clocks = profile.freeze(dp.RCC);
+        let timebase = Timebase::new(cp.DCB, cp.DWT, &clocks);
+
+        let gpioa = dp.GPIOA.split();
+        let gpiob = dp.GPIOB.split();
+        let gpioc = dp.GPIOC.split();
+
+        // LD2, off
+        let mut ld2 = gpioa.pa5.into_push_pull_output();
+        ld2.set_low();
+
+        // B1, pulled up on the board
+        let b1 = Button::new(gpioc.pc13, ActiveLevel::Low);
+
+        // Virtual COM port. 115200 baud is in range of every clock profile, so this cannot fail.
+        let vcp_config = Config::default().baudrate(115_200.bps());
+        let vcp = Serial::new(dp.USART2, (gpioa.pa2, gpioa.pa3), vcp_config, &clocks).unwrap();
+
+        // Arduino I2C
+        let scl = gpiob.pb8.into_alternate().set_open_drain();
+        let sda = gpiob.pb9.into_alternate().set_open_drain();
+        let i2c = I2c::new(dp.I2C1, (scl, sda), 100.kHz(), &clocks);
+
+        // A0
+        let a0 = gpioa.pa0.into_analog();
+        let adc = Adc::adc1(dp.ADC1, true, AdcConfig::default());
+
+        let header = Header {
+            d2: gpioa.pa10,
+            d3: gpiob.pb3,
+            d4: gpiob.pb5,
+            d5: gpiob.pb4,
+            d6: gpiob.pb10,
+            d7: gpioa.pa8,
+            d8: gpioa.pa9,
+            d9: gpioc.pc7,
+            d10: gpiob.pb6,
+            d11: gpioa.pa7,
+            d12: gpioa.pa6,
+            a1: gpioa.pa1,
+            a2: gpioa.pa4,
+            a3: gpiob.pb0,
+            a4: gpioc.pc1,
+            a5: gpioc.pc0,
+        };
+
+        Self {
+            clocks,
+            timebase,
+            ld2,
+            b1,
+            vcp,
+            i2c,
+            a0,
+            adc,
+            header,
+            TIM1: dp.TIM1,
+            TIM2: dp.TIM2,
+            TIM3: dp.TIM3,
+            TIM4: dp.TIM4,
+            TIM5: dp.TIM5,
+            TIM9: dp.TIM9,
+            TIM10: dp.TIM10,
+            TIM11: dp.TIM11,
+            DMA1: dp.DMA1,
+            DMA2: dp.DMA2,
+            EXTI: dp.EXTI,
+            SYSCFG: dp.SYSCFG,
+            NVIC: cp.NVIC,
+            SCB: cp.SCB,
+            SYST: cp.SYST,
+        }
+    }
+}
@@ -0,0 +1,474 @@
+// Interrupt-driven serial port with ring buffers.
+//
+// Polling `read` from the main loop loses bytes: the USART holds one received
+// byte, and the next one overruns it unless the loop comes back within a
+// character time, 87 µs at 115200 baud. Here the RXNE interrupt moves each byte
+// into a ring buffer as it arrives, and the TXE interrupt feeds the transmitter
+// from another, so the application reads and writes whole chunks whenever it
+// gets to it.
+//
+// Each ring is a heapless single-producer single-consumer queue: the interrupt
+// produces received bytes and the application consumes them, and the other way
+// around for bytes to send, so neither side takes a lock. The rings live in a
+// `SerialBuffers` that must be 'static, e.g. from `cortex_m::singleton!` or
+// RTIC's `#[init(local = [..])]`, and split into the application's
+// `BufferedSerial` and the handler's `SerialInterrupt`.
+//
+// Receive errors do not stop the stream: the byte is dropped and counted, as are
+// bytes that arrive with the receive ring full.
+
+// Imports
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::peripheral::NVIC;
+use embedded_hal_nb::serial::{self as nb_serial, Error as _, ErrorKind};
+use heapless::spsc::{Consumer, Producer, Queue};
+use stm32f4xx_hal::{
+    Listen, ReadFlags,
+    pac::{Interrupt, USART2},
+    serial::{self, Event, Flag, Serial},
+};
+
+/// Transmit interrupt the port raises.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum TxInterrupt {
+    /// None, everything has been sent.
+    #[default]
+    Off,
+    /// TXE: the data register can take the next byte.
+    Empty,
+    /// TC: the last byte has left the shift register.
+    Complete,
+}
+
+/// The USART operations the interrupt handler needs.
+///
+/// Implemented for the HAL's `Serial`, and by `mock::UartMock` for tests.
+pub trait UartPort {
+    /// Reads a received byte, `WouldBlock` if there is none.
+    fn read(&mut self) -> nb::Result<u8, ErrorKind>;
+
+    /// Loads a byte to send, `WouldBlock` while the data register is full.
+    fn write(&mut self, byte: u8) -> nb::Result<(), Infallible>;
+
+    /// True once every byte written has been sent.
+    fn is_tx_complete(&self) -> bool;
+
+    /// Enables or disables the RXNE interrupt, raised for received bytes and receive errors.
+    fn listen_rx(&mut self, enabled: bool);
+
+    /// Selects the transmit interrupt.
+    fn listen_tx(&mut self, interrupt: TxInterrupt);
+}
+
+impl<USART: serial::Instance> UartPort for Serial<USART> {
+    fn read(&mut self) -> nb::Result<u8, ErrorKind> {
+        nb_serial::Read::read(self).map_err(|e| e.map(|e| e.kind()))
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+        // The F4 USART has no transmit errors, only a full data register.
+        nb_serial::Write::write(self, byte).map_err(|_| nb::Error::WouldBlock)
+    }
+
+    fn is_tx_complete(&self) -> bool {
+        self.flags().contains(Flag::TransmissionComplete)
+    }
+
+    fn listen_rx(&mut self, enabled: bool) {
+        if enabled {
+            self.listen(Event::RxNotEmpty);
+        } else {
+            self.unlisten(Event::RxNotEmpty);
+        }
+    }
+
+    fn listen_tx(&mut self, interrupt: TxInterrupt) {
+        self.unlisten(Event::TxEmpty | Event::TransmissionComplete);
+        match interrupt {
+            TxInterrupt::Off => {}
+            TxInterrupt::Empty => self.listen(Event::TxEmpty),
+            TxInterrupt::Complete => self.listen(Event::TransmissionComplete),
+        }
+    }
+}
+
+/// Receive errors counted since the port was split.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub struct ErrorCounts {
+    /// A byte arrived before the last one was read: the interrupt ran too late.
+    pub overrun: u32,
+    /// No stop bit where one was due, e.g. a baud rate mismatch or a line break.
+    pub framing: u32,
+    /// Noise on the line while receiving.
+    pub noise: u32,
+    /// Parity check failed, with parity enabled.
+    pub parity: u32,
+    /// Arrived with the receive ring full: the application read too late.
+    pub dropped: u32,
+}
+
+// State shared by the two halves, written with atomics only.
+struct Status {
+    overrun: AtomicU32,
+    framing: AtomicU32,
+    noise: AtomicU32,
+    parity: AtomicU32,
+    dropped: AtomicU32,
+    // Set by the interrupt once the transmit ring is empty and the last byte has been sent.
+    tx_idle: AtomicBool,
+}
+
+impl Status {
+    const fn new() -> Self {
+        Self {
+            overrun: AtomicU32::new(0),
+            framing: AtomicU32::new(0),
+            noise: AtomicU32::new(0),
+            parity: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+            tx_idle: AtomicBool::new(true),
+        }
+    }
+
+    fn count(counter: &AtomicU32) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count_error(&self, kind: ErrorKind) {
+        match kind {
+            ErrorKind::Overrun => Self::count(&self.overrun),
+            ErrorKind::Parity => Self::count(&self.parity),
+            ErrorKind::Noise => Self::count(&self.noise),
+            // Framing and anything the HAL cannot tell apart.
+            _ => Self::count(&self.framing),
+        }
+    }
+
+    fn errors(&self) -> ErrorCounts {
+        ErrorCounts {
+            overrun: self.overrun.load(Ordering::Relaxed),
+            framing: self.framing.load(Ordering::Relaxed),
+            noise: self.noise.load(Ordering::Relaxed),
+            parity: self.parity.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Ring buffers for a `BufferedSerial`, `RX` and `TX` bytes. Each ring holds one byte less than its size.
+pub struct SerialBuffers<const RX: usize, const TX: usize> {
+    rx: Queue<u8, RX>,
+    tx: Queue<u8, TX>,
+    status: Status,
+}
+
+impl<const RX: usize, const TX: usize> SerialBuffers<RX, TX> {
+    // Constructor
+    pub const fn new() -> Self {
+        Self { rx: Queue::new(), tx: Queue::new(), status: Status::new() }
+    }
+
+    /// Splits the buffers into the application and interrupt halves on `port`, and
+    /// enables its receive interrupt.
+    ///
+    /// `pend` must raise the port's interrupt, so written bytes start sending.
+    pub fn split<PORT: UartPort>(
+        &'static mut self,
+        mut port: PORT,
+        pend: fn(),
+    ) -> (BufferedSerial<RX, TX>, SerialInterrupt<PORT, RX, TX>) {
+        let SerialBuffers { rx, tx, status } = self;
+        let (rx_producer, rx_consumer) = rx.split();
+        let (tx_producer, tx_consumer) = tx.split();
+        let status: &'static Status = status;
+
+        port.listen_tx(TxInterrupt::Off);
+        port.listen_rx(true);
+        let serial = BufferedSerial { rx: rx_consumer, tx: tx_producer, status, pend };
+        let interrupt = SerialInterrupt { port, rx: rx_producer, tx: tx_consumer, status };
+        (serial, interrupt)
+    }
+}
+
+impl<const RX: usize, const TX: usize> Default for SerialBuffers<RX, TX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Application half of a buffered serial port.
+pub struct BufferedSerial<const RX: usize, const TX: usize> {
+    rx: Consumer<'static, u8, RX>,
+    tx: Producer<'static, u8, TX>,
+    status: &'static Status,
+    pend: fn(),
+}
+
+impl<const RX: usize, const TX: usize> BufferedSerial<RX, TX> {
+    /// Buffers USART2, e.g. the board's `vcp`.
+    ///
+    /// Call `on_interrupt` on the returned half from the USART2 handler, and unmask USART2 in the NVIC.
+    pub fn usart2(
+        serial: Serial<USART2>,
+        buffers: &'static mut SerialBuffers<RX, TX>,
+    ) -> (Self, SerialInterrupt<Serial<USART2>, RX, TX>) {
+        buffers.split(serial, || NVIC::pend(Interrupt::USART2))
+    }
+
+    /// Reads the bytes received so far into `buf`, without waiting. Returns how many were read.
+    pub fn try_read(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        for slot in buf.iter_mut() {
+            match self.rx.dequeue() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Queues as much of `buf` as fits, without waiting, and starts sending. Returns how many were queued.
+    pub fn try_write(&mut self, buf: &[u8]) -> usize {
+        let mut count = 0;
+        for &byte in buf {
+            if self.tx.enqueue(byte).is_err() {
+                break;
+            }
+            count += 1;
+        }
+        if count > 0 {
+            (self.pend)();
+        }
+        count
+    }
+
+    /// Received bytes waiting to be read.
+    pub fn available(&self) -> usize {
+        self.rx.len()
+    }
+
+    /// True while written bytes are still queued or being sent.
+    pub fn is_sending(&self) -> bool {
+        self.tx.len() > 0 || !self.status.tx_idle.load(Ordering::Acquire)
+    }
+
+    /// Receive errors so far.
+    pub fn errors(&self) -> ErrorCounts {
+        self.status.errors()
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::ErrorType for BufferedSerial<RX, TX> {
+    type Error = Infallible;
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::Read for BufferedSerial<RX, TX> {
+    /// Waits for at least one byte, then reads what has been received, up to `buf.len()`.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let count = self.try_read(buf);
+            if count > 0 {
+                return Ok(count);
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::ReadReady for BufferedSerial<RX, TX> {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(self.rx.ready())
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::Write for BufferedSerial<RX, TX> {
+    /// Waits for room for at least one byte, then queues what fits, up to `buf.len()`.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let count = self.try_write(buf);
+            if count > 0 {
+                return Ok(count);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Waits until every byte written has been sent.
+    fn flush(&mut self) -> Result<(), Infallible> {
+        while self.is_sending() {
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+}
+
+impl<const RX: usize, const TX: usize> embedded_io::WriteReady for BufferedSerial<RX, TX> {
+    fn write_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(self.tx.ready())
+    }
+}
+
+/// Interrupt half of a buffered serial port, owning the USART.
+pub struct SerialInterrupt<PORT, const RX: usize, const TX: usize> {
+    port: PORT,
+    rx: Producer<'static, u8, RX>,
+    tx: Consumer<'static, u8, TX>,
+    status: &'static Status,
+}
+
+impl<PORT: UartPort, const RX: usize, const TX: usize> SerialInterrupt<PORT, RX, TX> {
+    /// Moves received bytes into the receive ring, and the next byte to send out of
+    /// the transmit ring. Call from the USART interrupt handler.
+    pub fn on_interrupt(&mut self) {
+        loop {
+            match self.port.read() {
+                Ok(byte) => {
+                    if self.rx.enqueue(byte).is_err() {
+                        Status::count(&self.status.dropped);
+                    }
+                }
+                Err(nb::Error::Other(kind)) => self.status.count_error(kind),
+                Err(nb::Error::WouldBlock) => break,
+            }
+        }
+
+        while let Some(&byte) = self.tx.peek() {
+            if self.port.write(byte).is_err() {
+                break;
+            }
+            self.tx.dequeue();
+        }
+
+        // TXE while there is more to send, then TC to see the last byte out.
+        let interrupt = if self.tx.ready() {
+            TxInterrupt::Empty
+        } else if self.port.is_tx_complete() {
+            TxInterrupt::Off
+        } else {
+            TxInterrupt::Complete
+        };
+        self.status.tx_idle.store(interrupt == TxInterrupt::Off, Ordering::Release);
+        self.port.listen_tx(interrupt);
+    }
+
+    // Release port, with its interrupts disabled
+    pub fn release(mut self) -> PORT {
+        self.port.listen_rx(false);
+        self.port.listen_tx(TxInterrupt::Off);
+        self.port
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::UartMock;
+    use core::sync::atomic::AtomicUsize;
+    use std::boxed::Box;
+
+    static PENDS: AtomicUsize = AtomicUsize::new(0);
+
+    fn pend() {
+        PENDS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn split<const RX: usize, const TX: usize>() -> (BufferedSerial<RX, TX>, SerialInterrupt<UartMock, RX, TX>) {
+        let buffers: &'static mut SerialBuffers<RX, TX> = Box::leak(Box::new(SerialBuffers::new()));
+        buffers.split(UartMock::default(), pend)
+    }
+
+    #[test]
+    fn burst_is_buffered_without_loss() {
+        let (mut serial, mut irq) = split::<64, 16>();
+        assert!(irq.port.rx_listening);
+        for byte in b"thunder 42\nthunder 43\nthunder 44\n" {
+            irq.port.receive(*byte);
+            irq.on_interrupt();
+        }
+        let mut buf = [0u8; 64];
+        let count = serial.try_read(&mut buf);
+        assert_eq!(&buf[..count], b"thunder 42\nthunder 43\nthunder 44\n");
+        assert_eq!(serial.try_read(&mut buf), 0);
+        assert_eq!(serial.errors(), ErrorCounts::default());
+    }
+
+    #[test]
+    fn errors_are_counted_and_stream_continues() {
+        let (mut serial, mut irq) = split::<16, 16>();
+        irq.port.receive(b'a');
+        irq.port.receive_error(ErrorKind::Overrun);
+        irq.port.receive(b'b');
+        irq.port.receive_error(ErrorKind::FrameFormat);
+        irq.port.receive_error(ErrorKind::Noise);
+        irq.port.receive_error(ErrorKind::Parity);
+        irq.port.receive(b'c');
+        irq.on_interrupt();
+
+        let mut buf = [0u8; 8];
+        let count = serial.try_read(&mut buf);
+        assert_eq!(&buf[..count], b"abc");
+        let errors = ErrorCounts { overrun: 1, framing: 1, noise: 1, parity: 1, dropped: 0 };
+        assert_eq!(serial.errors(), errors);
+    }
+
+    #[test]
+    fn full_receive_ring_drops_and_counts() {
+        let (mut serial, mut irq) = split::<8, 8>();
+        for byte in 0..10 {
+            irq.port.receive(byte);
+        }
+        irq.on_interrupt();
+        assert_eq!(serial.available(), 7);
+        assert_eq!(serial.errors().dropped, 3);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(embedded_io::Read::read(&mut serial, &mut buf), Ok(4));
+        assert_eq!(buf, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn sends_through_interrupts() {
+        let (mut serial, mut irq) = split::<8, 16>();
+        let pends = PENDS.load(Ordering::Relaxed);
+        assert_eq!(serial.try_write(b"hello"), 5);
+        assert!(PENDS.load(Ordering::Relaxed) > pends);
+        assert!(serial.is_sending());
+
+        // The pended interrupt loads the first byte and waits for TXE.
+        irq.on_interrupt();
+        assert_eq!(irq.port.tx_interrupt, TxInterrupt::Empty);
+        while irq.port.tx_interrupt == TxInterrupt::Empty {
+            irq.port.shift_out();
+            irq.on_interrupt();
+        }
+
+        // The last byte is loaded, but not sent until TC.
+        assert_eq!(irq.port.tx_interrupt, TxInterrupt::Complete);
+        assert!(serial.is_sending());
+        irq.port.shift_out();
+        irq.on_interrupt();
+        assert_eq!(irq.port.tx_interrupt, TxInterrupt::Off);
+        assert!(!serial.is_sending());
+        assert_eq!(embedded_io::Write::flush(&mut serial), Ok(()));
+        assert_eq!(irq.release().sent, b"hello");
+    }
+
+    #[test]
+    fn write_queues_what_fits() {
+        let (mut serial, _irq) = split::<8, 4>();
+        assert_eq!(serial.try_write(b"abcdef"), 3);
+        assert_eq!(embedded_io::WriteReady::write_ready(&mut serial), Ok(false));
+        assert_eq!(serial.try_write(b"gh"), 0);
+        assert_eq!(embedded_io::Write::write(&mut serial, &[]), Ok(0));
+    }
+}
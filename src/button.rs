@@ -0,0 +1,273 @@
+// Debounced push button with click gestures.
+//
+// The pin is sampled periodically, from a timer tick, an RTIC task or a main
+// loop, with a free-running millisecond timestamp. A level only counts once it
+// has been stable for the debounce time, so contact bounce never reaches the
+// gesture logic. A short press is held back as a possible first half of a double
+// click: `Click` comes once the double-click window has passed without a second
+// press, `DoubleClick` on the release of the second press. Holding past the
+// long-press time gives `LongPress` while the button is still down, and no click.
+
+// Imports
+use embedded_hal::digital::InputPin;
+use heapless::Vec;
+
+/// Pin level of a pressed button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum ActiveLevel {
+    /// Pressed pulls the pin low, e.g. B1 on the Nucleo boards.
+    Low,
+    /// Pressed pulls the pin high.
+    High,
+}
+
+/// Something the button did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum Event {
+    /// The button went down.
+    Pressed,
+    /// The button came up.
+    Released,
+    /// A short press, not followed by a second one within the double-click window.
+    Click,
+    /// Two short presses within the double-click window.
+    DoubleClick,
+    /// The button has been held this many milliseconds, the long-press time or a tick more.
+    LongPress(u32),
+}
+
+/// Events from one sample, oldest first.
+pub type Events = Vec<Event, 3>;
+
+/// Debounced button on an input pin.
+pub struct Button<PIN> {
+    pin: PIN,
+    active: ActiveLevel,
+    debounce_ms: u32,
+    double_click_ms: u32,
+    long_press_ms: u32,
+    raw: bool,
+    raw_since_ms: u32,
+    pressed: bool,
+    pressed_at_ms: u32,
+    long_press_sent: bool,
+    click_released_at_ms: Option<u32>,
+}
+
+impl<PIN: InputPin> Button<PIN> {
+    /// Creates a released button with 20 ms debounce, 300 ms double-click window and 800 ms long press.
+    pub fn new(pin: PIN, active: ActiveLevel) -> Self {
+        Self {
+            pin,
+            active,
+            debounce_ms: 20,
+            double_click_ms: 300,
+            long_press_ms: 800,
+            raw: false,
+            raw_since_ms: 0,
+            pressed: false,
+            pressed_at_ms: 0,
+            long_press_sent: false,
+            click_released_at_ms: None,
+        }
+    }
+
+    /// Time a level must be stable to count.
+    pub fn with_debounce_ms(mut self, debounce_ms: u32) -> Self {
+        self.debounce_ms = debounce_ms;
+        self
+    }
+
+    /// Longest gap between the release of the first press and the second press of a double click.
+    ///
+    /// 0 disables double clicks, so `Click` comes straight on release.
+    pub fn with_double_click_ms(mut self, double_click_ms: u32) -> Self {
+        self.double_click_ms = double_click_ms;
+        self
+    }
+
+    /// Time a press must be held to become a long press.
+    pub fn with_long_press_ms(mut self, long_press_ms: u32) -> Self {
+        self.long_press_ms = long_press_ms.max(1);
+        self
+    }
+
+    /// Samples the pin at `now_ms` and returns what happened.
+    ///
+    /// `now_ms` is a free-running millisecond counter that may wrap. Sample at least
+    /// every few milliseconds, well inside the debounce time.
+    pub fn update(&mut self, now_ms: u32) -> Result<Events, PIN::Error> {
+        let raw = match self.active {
+            ActiveLevel::Low => self.pin.is_low()?,
+            ActiveLevel::High => self.pin.is_high()?,
+        };
+        let mut events = Events::new();
+        let mut emit = |event| {
+            // Never more than three per sample: Click, Pressed or LongPress, Released, DoubleClick.
+            let _ = events.push(event);
+        };
+
+        if raw != self.raw {
+            self.raw = raw;
+            self.raw_since_ms = now_ms;
+        }
+
+        // The double-click window ran out while released, so the last press was a single click.
+        if let Some(released_at) = self.click_released_at_ms
+            && !self.pressed
+            && now_ms.wrapping_sub(released_at) > self.double_click_ms
+        {
+            self.click_released_at_ms = None;
+            emit(Event::Click);
+        }
+
+        if raw != self.pressed && now_ms.wrapping_sub(self.raw_since_ms) >= self.debounce_ms {
+            self.pressed = raw;
+            if raw {
+                self.pressed_at_ms = now_ms;
+                self.long_press_sent = false;
+                emit(Event::Pressed);
+            } else {
+                emit(Event::Released);
+                if !self.long_press_sent {
+                    if self.click_released_at_ms.take().is_some() {
+                        emit(Event::DoubleClick);
+                    } else if self.double_click_ms == 0 {
+                        emit(Event::Click);
+                    } else {
+                        self.click_released_at_ms = Some(now_ms);
+                    }
+                }
+            }
+        }
+
+        let held_ms = now_ms.wrapping_sub(self.pressed_at_ms);
+        if self.pressed && !self.long_press_sent && held_ms >= self.long_press_ms {
+            self.long_press_sent = true;
+            // A click before this press was not the start of a double click after all.
+            if self.click_released_at_ms.take().is_some() {
+                emit(Event::Click);
+            }
+            emit(Event::LongPress(held_ms));
+        }
+
+        Ok(events)
+    }
+
+    /// True while the debounced button is down.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    // Release pin
+    pub fn release(self) -> PIN {
+        self.pin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::PinMock;
+    use std::vec::Vec;
+
+    const TICK_MS: u32 = 5;
+
+    // Button on an active-low pin, sampled every 5 ms.
+    struct Bench {
+        button: Button<PinMock>,
+        now_ms: u32,
+    }
+
+    impl Bench {
+        fn new() -> Self {
+            Self::starting_at(0)
+        }
+
+        fn starting_at(now_ms: u32) -> Self {
+            let button = Button::new(PinMock { high: true }, ActiveLevel::Low);
+            Self { button, now_ms }
+        }
+
+        // Holds the button down (or up) for `ms`, collecting the events.
+        fn hold(&mut self, down: bool, ms: u32) -> Vec<Event> {
+            self.button.pin.high = !down;
+            let mut events = Vec::new();
+            for _ in 0..ms / TICK_MS {
+                self.now_ms = self.now_ms.wrapping_add(TICK_MS);
+                events.extend(self.button.update(self.now_ms).unwrap());
+            }
+            events
+        }
+    }
+
+    #[test]
+    fn bounces_are_filtered() {
+        let mut bench = Bench::new();
+        // Contact bounce shorter than the debounce time.
+        for _ in 0..5 {
+            assert!(bench.hold(true, 10).is_empty());
+            assert!(bench.hold(false, 10).is_empty());
+        }
+        assert_eq!(bench.hold(true, 50), [Event::Pressed]);
+        assert!(bench.button.is_pressed());
+        assert!(bench.hold(false, 10).is_empty());
+        assert!(bench.hold(true, 10).is_empty());
+        assert_eq!(bench.hold(false, 30), [Event::Released]);
+    }
+
+    #[test]
+    fn click_after_double_click_window() {
+        let mut bench = Bench::new();
+        assert_eq!(bench.hold(true, 100), [Event::Pressed]);
+        assert_eq!(bench.hold(false, 200), [Event::Released]);
+        assert_eq!(bench.hold(false, 200), [Event::Click]);
+        assert!(bench.hold(false, 1000).is_empty());
+    }
+
+    #[test]
+    fn double_click() {
+        let mut bench = Bench::new();
+        bench.hold(true, 100);
+        bench.hold(false, 100);
+        assert_eq!(bench.hold(true, 100), [Event::Pressed]);
+        assert_eq!(bench.hold(false, 1000), [Event::Released, Event::DoubleClick]);
+    }
+
+    #[test]
+    fn long_press_while_held() {
+        let mut bench = Bench::new();
+        // Pin low from the 5 ms sample, debounced at 25 ms, long press 800 ms later.
+        assert_eq!(bench.hold(true, 820), [Event::Pressed]);
+        assert_eq!(bench.hold(true, 5), [Event::LongPress(800)]);
+        assert!(bench.hold(true, 2000).is_empty());
+        assert_eq!(bench.hold(false, 1000), [Event::Released]);
+    }
+
+    #[test]
+    fn click_then_long_press() {
+        let mut bench = Bench::new();
+        bench.hold(true, 100);
+        bench.hold(false, 100);
+        assert_eq!(bench.hold(true, 900), [Event::Pressed, Event::Click, Event::LongPress(800)]);
+        assert_eq!(bench.hold(false, 1000), [Event::Released]);
+    }
+
+    #[test]
+    fn timestamps_may_wrap() {
+        let mut bench = Bench::starting_at(u32::MAX - 50);
+        bench.button = Button::new(PinMock { high: true }, ActiveLevel::Low).with_double_click_ms(0);
+        assert_eq!(bench.hold(true, 100), [Event::Pressed]);
+        assert_eq!(bench.hold(false, 100), [Event::Released, Event::Click]);
+    }
+
+    #[test]
+    fn active_high() {
+        let mut button = Button::new(PinMock { high: false }, ActiveLevel::High).with_debounce_ms(0);
+        assert!(button.update(0).unwrap().is_empty());
+        button.pin.high = true;
+        assert_eq!(button.update(1).unwrap(), [Event::Pressed]);
+    }
+}
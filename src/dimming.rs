@@ -0,0 +1,246 @@
+// Perceptually linear LED dimming.
+//
+// The eye's response to light is far from linear: 10% duty looks about a third
+// as bright as full, and the steps near full hardly show. A brightness curve maps
+// the brightness asked for onto the luminance, and so the duty, that looks that
+// bright. The curves are tables of 257 points built by `const fn` at compile
+// time, interpolated at run time, and scaled onto the timer's own maximum duty.
+//
+// At low brightness one duty step is a visible jump. With dithering on, the duty
+// alternates between the two nearest steps so the average lands between them;
+// call `refresh` regularly, ideally from the timer update interrupt, for that.
+
+// Imports
+use embedded_hal::pwm::SetDutyCycle;
+
+/// Points in a curve, brightness 0/256 to 256/256.
+pub const CURVE_LEN: usize = 257;
+
+/// Luminance (0 - 65535) at evenly spaced brightness steps.
+pub type Curve = [u16; CURVE_LEN];
+
+/// CIE 1931 lightness: equal brightness steps look equal.
+pub const CIE1931: Curve = cie1931_curve();
+
+/// Gamma 2.2, the sRGB approximation.
+pub const GAMMA_2_2: Curve = gamma_curve(2.2);
+
+/// Builds the CIE 1931 curve, L* = 100 * brightness.
+pub const fn cie1931_curve() -> Curve {
+    let mut curve = [0; CURVE_LEN];
+    let mut i = 0;
+    while i < CURVE_LEN {
+        let lightness = 100.0 * i as f64 / (CURVE_LEN - 1) as f64;
+        let luminance = if lightness <= 8.0 {
+            lightness / 903.3
+        } else {
+            let x = (lightness + 16.0) / 116.0;
+            x * x * x
+        };
+        curve[i] = to_u16(luminance);
+        i += 1;
+    }
+    curve
+}
+
+/// Builds a gamma curve, luminance = brightness ^ `gamma`.
+pub const fn gamma_curve(gamma: f64) -> Curve {
+    let mut curve = [0; CURVE_LEN];
+    let mut i = 0;
+    while i < CURVE_LEN {
+        curve[i] = to_u16(const_powf(i as f64 / (CURVE_LEN - 1) as f64, gamma));
+        i += 1;
+    }
+    curve
+}
+
+/// Luminance (0 - 65535) for a brightness in 1/65536ths, interpolated from `curve`.
+pub fn luminance(curve: &Curve, brightness: u16) -> u16 {
+    // Position on the curve in 1/256ths of a point, 0..=65536, so 65535 is the last point.
+    let position = (brightness as u32 * 65536 + 32767) / 65535;
+    let index = (position >> 8) as usize;
+    if index >= CURVE_LEN - 1 {
+        return curve[CURVE_LEN - 1];
+    }
+    let frac = (position & 0xFF) as i32;
+    let low = curve[index] as i32;
+    let high = curve[index + 1] as i32;
+    (low + ((high - low) * frac + 128) / 256) as u16
+}
+
+// Rounds 0.0 - 1.0 onto 0 - 65535.
+const fn to_u16(x: f64) -> u16 {
+    (x * 65535.0 + 0.5) as u16
+}
+
+// x ^ y for x in 0.0 - 1.0. Core has no powf, in const or otherwise: the whole
+// part of y is repeated multiplication, the fraction is taken bit by bit from
+// repeated square roots of x.
+const fn const_powf(x: f64, y: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut result = 1.0;
+    let whole = y as u32;
+    let mut i = 0;
+    while i < whole {
+        result *= x;
+        i += 1;
+    }
+    let mut frac = y - whole as f64;
+    let mut root = x;
+    let mut bit = 0;
+    while bit < 30 {
+        root = const_sqrt(root);
+        frac *= 2.0;
+        if frac >= 1.0 {
+            result *= root;
+            frac -= 1.0;
+        }
+        bit += 1;
+    }
+    result
+}
+
+// Newton's method, starting above the root so it converges from above.
+const fn const_sqrt(x: f64) -> f64 {
+    let mut y = if x > 1.0 { x } else { 1.0 };
+    let mut i = 0;
+    while i < 40 {
+        y = 0.5 * (y + x / y);
+        i += 1;
+    }
+    y
+}
+
+/// LED on a PWM channel, dimmed along a brightness curve.
+pub struct Dimmer<PWM> {
+    pwm: PWM,
+    curve: &'static Curve,
+    dithering: bool,
+    // Duty in 1/256ths of a step, and the dither error carried over.
+    duty_256: u32,
+    error: u32,
+}
+
+impl<PWM: SetDutyCycle> Dimmer<PWM> {
+    /// Creates a dimmer on the CIE 1931 curve, with the LED off. The channel must be enabled.
+    pub fn new(pwm: PWM) -> Result<Self, PWM::Error> {
+        let mut dimmer = Self { pwm, curve: &CIE1931, dithering: false, duty_256: 0, error: 0 };
+        dimmer.refresh()?;
+        Ok(dimmer)
+    }
+
+    /// Selects the brightness curve, e.g. `GAMMA_2_2` or one from `gamma_curve`.
+    pub fn with_curve(mut self, curve: &'static Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Dithers between duty steps for brightness finer than one step. Needs regular `refresh` calls.
+    pub fn with_dithering(mut self) -> Self {
+        self.dithering = true;
+        self
+    }
+
+    /// Sets the brightness, 0.0 (off) to 1.0 (full).
+    pub fn set_brightness(&mut self, brightness: f32) -> Result<(), PWM::Error> {
+        // NaN casts to 0, so it turns the LED off.
+        let brightness = (brightness.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16;
+        self.set_brightness_q16(brightness)
+    }
+
+    /// Sets the brightness from a 12-bit value, 0 (off) to 4095 (full), e.g. an ADC sample.
+    pub fn set_brightness_12bit(&mut self, brightness: u16) -> Result<(), PWM::Error> {
+        let brightness = brightness.min(4095);
+        // Spread 0..=4095 onto 0..=65535 by repeating the top bits.
+        self.set_brightness_q16((brightness << 4) | (brightness >> 8))
+    }
+
+    /// Sets the brightness in 1/65536ths, 0 (off) to 65535 (full).
+    pub fn set_brightness_q16(&mut self, brightness: u16) -> Result<(), PWM::Error> {
+        let luminance = luminance(self.curve, brightness) as u64;
+        let max_duty = self.pwm.max_duty_cycle() as u64;
+        self.duty_256 = ((luminance * max_duty * 256 + 32767) / 65535) as u32;
+        self.refresh()
+    }
+
+    /// Writes the duty for the next period. With dithering, call this at a steady rate.
+    pub fn refresh(&mut self) -> Result<(), PWM::Error> {
+        let duty = if self.dithering {
+            // First-order sigma-delta: carry the fraction over until it adds up to a step.
+            let sum = self.error + (self.duty_256 & 0xFF);
+            self.error = sum & 0xFF;
+            (self.duty_256 >> 8) + (sum >> 8)
+        } else {
+            (self.duty_256 + 128) >> 8
+        };
+        self.pwm.set_duty_cycle(duty as u16)
+    }
+
+    // Release PWM channel
+    pub fn release(self) -> PWM {
+        self.pwm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::PwmMock;
+
+    #[test]
+    fn curves_are_monotonic_and_span_full_range() {
+        for curve in [&CIE1931, &GAMMA_2_2, &gamma_curve(1.0)] {
+            assert_eq!(curve[0], 0);
+            assert_eq!(curve[CURVE_LEN - 1], 65535);
+            assert!(curve.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+        // Linear curve is the identity, up to rounding.
+        assert_eq!(gamma_curve(1.0)[128], 32768);
+    }
+
+    #[test]
+    fn compile_time_curves_match_reference() {
+        for (i, &point) in GAMMA_2_2.iter().enumerate() {
+            let x = i as f64 / 256.0;
+            let gamma = (libm::pow(x, 2.2) * 65535.0 + 0.5) as i32;
+            assert!((point as i32 - gamma).abs() <= 1, "{}: {} vs {}", i, point, gamma);
+        }
+        // Half lightness is 18.4% luminance.
+        assert_eq!(CIE1931[128], (0.184187 * 65535.0 + 0.5) as u16);
+        assert!(luminance(&CIE1931, 0x8000).abs_diff(CIE1931[128]) <= 1);
+        assert_eq!(luminance(&CIE1931, 0xFFFF), 65535);
+    }
+
+    #[test]
+    fn scales_to_max_duty() {
+        let mut dimmer = Dimmer::new(PwmMock::new(1000)).unwrap().with_curve(&GAMMA_2_2);
+        dimmer.set_brightness(1.0).unwrap();
+        assert_eq!(dimmer.pwm.duty, 1000);
+        dimmer.set_brightness(0.5).unwrap();
+        assert_eq!(dimmer.pwm.duty, 218);
+        dimmer.set_brightness_12bit(4095).unwrap();
+        assert_eq!(dimmer.pwm.duty, 1000);
+        dimmer.set_brightness_12bit(0).unwrap();
+        assert_eq!(dimmer.pwm.duty, 0);
+        dimmer.set_brightness(f32::NAN).unwrap();
+        assert_eq!(dimmer.release().duty, 0);
+    }
+
+    #[test]
+    fn dithering_averages_between_steps() {
+        let mut dimmer = Dimmer::new(PwmMock::new(100)).unwrap().with_curve(&CIE1931).with_dithering();
+        // 3% brightness is 0.33 duty steps on a 100-step timer.
+        dimmer.set_brightness(0.03).unwrap();
+        let expected = dimmer.duty_256 as f32 / 256.0;
+        let mut total = dimmer.pwm.duty as u32;
+        for _ in 1..256 {
+            dimmer.refresh().unwrap();
+            assert!(dimmer.pwm.duty <= 1);
+            total += dimmer.pwm.duty as u32;
+        }
+        assert!((total as f32 / 256.0 - expected).abs() < 1.0 / 256.0);
+        assert!(expected > 0.2 && expected < 0.5);
+    }
+}
@@ -0,0 +1,498 @@
+// Serial port on DMA, for high-rate telemetry.
+//
+// `BufferedSerial` takes an interrupt per byte, which adds up at high rates. Here
+// the DMA moves the bytes and the CPU only hears about whole frames: USART2 TX is
+// on DMA1 stream 6 and RX on DMA1 stream 5, both channel 4.
+//
+// Transmit is double-buffered: one frame is sent from a static buffer while the
+// control loop fills the other, so `send` copies the frame and returns at once.
+// The stream's transfer complete interrupt starts a frame queued behind the one
+// being sent.
+//
+// Receive runs in circular mode: the stream writes round and round a ring buffer
+// and never stops, so no byte waits on the CPU. When the line goes idle, one
+// character time after the last byte, the handler copies the bytes between its
+// read position and the stream's (from NDTR) into a chunk buffer and hands them
+// to a parser, so every chunk arrives whole, without per-byte interrupts. The
+// half and full transfer interrupts drain the ring too, so the handler runs at
+// least every half ring. Bytes are only lost if it is held off for longer than
+// that, half a ring of character times, and the stream laps the read position.
+// The ring is made of atomics, so it can be read while the stream writes it.
+//
+// A frame is sent from a prefix of its buffer, which the HAL's buffer types cannot
+// express without giving up the rest of it, so `TxFrame` and `RxRing` are the DMA
+// buffer types implemented here. Their impls, and switching the receive stream to
+// circular mode, are the only unsafe code in the library.
+
+// Imports
+use core::sync::atomic::{compiler_fence, AtomicU8, Ordering};
+use embedded_dma::{ReadBuffer, WriteBuffer};
+use stm32f4xx_hal::{
+    ClearFlags, ReadFlags,
+    dma::{
+        ChannelX, DmaFlag, MemoryToPeripheral, PeripheralToMemory, Stream5, Stream6, Transfer,
+        config::DmaConfig,
+        traits::{Channel, DMASet, DmaFlagExt, Stream},
+    },
+    pac::{DMA1, USART2},
+    rcc::Clocks,
+    serial::{
+        self, Rx, RxISR, RxListen, Serial, Tx,
+        config::{Config, DmaConfig as SerialDmaConfig, InvalidConfig},
+    },
+};
+
+/// Frame in a static transmit buffer: the first `len` bytes are sent.
+pub struct TxFrame<const N: usize> {
+    buf: &'static mut [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> TxFrame<N> {
+    /// An empty frame in `buf`.
+    pub fn new(buf: &'static mut [u8; N]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// The bytes to send.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+// SAFETY: the pointer and length are those of the frame's bytes in the 'static
+// buffer, which the frame owns, and only change through `&mut self`.
+#[allow(unsafe_code)]
+unsafe impl<const N: usize> ReadBuffer for TxFrame<N> {
+    type Word = u8;
+
+    unsafe fn read_buffer(&self) -> (*const u8, usize) {
+        (self.buf.as_ptr(), self.len)
+    }
+}
+
+/// Receive ring that the DMA stream writes round and round, and `DmaRx` reads behind it.
+#[derive(Clone, Copy)]
+pub struct RxRing<const N: usize> {
+    buf: &'static [AtomicU8; N],
+}
+
+impl<const N: usize> RxRing<N> {
+    /// A ring over `buf`.
+    pub fn new(buf: &'static [AtomicU8; N]) -> Self {
+        Self { buf }
+    }
+
+    /// The byte at `index`, 0..N.
+    pub fn read(&self, index: usize) -> u8 {
+        self.buf[index].load(Ordering::Relaxed)
+    }
+}
+
+// SAFETY: the pointer and length are those of the 'static ring, which never moves.
+// `AtomicU8` has the layout of `u8` and allows writes behind a shared reference.
+#[allow(unsafe_code)]
+unsafe impl<const N: usize> WriteBuffer for RxRing<N> {
+    type Word = u8;
+
+    unsafe fn write_buffer(&mut self) -> (*mut u8, usize) {
+        (self.buf.as_ptr() as *mut u8, N)
+    }
+}
+
+/// The DMA transmit stream operations `DmaTx` needs.
+///
+/// Implemented for the HAL's serial `Transfer`, and by `mock::TxChannelMock` for tests.
+pub trait TxChannel<const N: usize> {
+    /// Starts sending `frame` and returns the frame sent before. Only called once that one is done.
+    fn start(&mut self, frame: TxFrame<N>) -> TxFrame<N>;
+
+    /// True, once, when the frame started last has been sent.
+    fn take_complete(&mut self) -> bool;
+}
+
+impl<STREAM, const CH: u8, USART, const N: usize> TxChannel<N>
+    for Transfer<STREAM, CH, Tx<USART>, MemoryToPeripheral, TxFrame<N>>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+    USART: serial::Instance,
+    Tx<USART>: DMASet<STREAM, CH, MemoryToPeripheral>,
+{
+    fn start(&mut self, frame: TxFrame<N>) -> TxFrame<N> {
+        // Without double buffering the HAL stops the stream and swaps, so this cannot fail.
+        match self.next_transfer(frame) {
+            Ok((previous, _)) => previous,
+            Err(_) => unreachable!("next_transfer only fails when double buffering"),
+        }
+    }
+
+    fn take_complete(&mut self) -> bool {
+        let complete = self.flags().is_transfer_complete();
+        if complete {
+            self.clear_flags(DmaFlag::TransferComplete);
+        }
+        complete
+    }
+}
+
+/// The circular DMA receive stream operations `DmaRx` needs.
+///
+/// Implemented for the HAL's serial `Transfer`, and by `mock::RxChannelMock` for tests.
+pub trait RxChannel {
+    /// Clears the pending interrupt flags. True, once, when the line has gone idle.
+    fn take_idle(&mut self) -> bool;
+
+    /// Ring index the stream writes next.
+    fn write_index(&self) -> usize;
+}
+
+impl<STREAM, const CH: u8, USART, const N: usize> RxChannel
+    for Transfer<STREAM, CH, Rx<USART>, PeripheralToMemory, RxRing<N>>
+where
+    STREAM: Stream,
+    ChannelX<CH>: Channel,
+    USART: serial::Instance,
+    Rx<USART>: DMASet<STREAM, CH, PeripheralToMemory> + RxISR,
+{
+    fn take_idle(&mut self) -> bool {
+        // Half and full transfer only wake the handler up to drain the ring.
+        self.clear_flags(DmaFlag::HalfTransfer | DmaFlag::TransferComplete);
+        let idle = self.is_idle();
+        if idle {
+            self.clear_idle_interrupt();
+        }
+        idle
+    }
+
+    fn write_index(&self) -> usize {
+        // NDTR counts down from N and reloads at the end of the ring.
+        let index = (N - self.number_of_transfers() as usize) % N;
+        // The ring reads that follow must not move above the NDTR read.
+        compiler_fence(Ordering::Acquire);
+        index
+    }
+}
+
+/// Why a frame was not queued.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum SendError {
+    /// A frame is already queued behind the one being sent.
+    Busy,
+    /// The frame is longer than the buffer.
+    TooLong,
+}
+
+/// Double-buffered DMA transmitter: one frame is sent while the next is queued.
+pub struct DmaTx<CH, const N: usize> {
+    channel: CH,
+    // The frame to fill, or the one queued when `queued`. Always Some outside `update`.
+    next: Option<TxFrame<N>>,
+    queued: bool,
+    sending: bool,
+}
+
+impl<CH: TxChannel<N>, const N: usize> DmaTx<CH, N> {
+    /// Sends on `channel`, filling `frame` while it holds another one.
+    pub fn new(channel: CH, frame: TxFrame<N>) -> Self {
+        Self { channel, next: Some(frame), queued: false, sending: false }
+    }
+
+    /// Copies `bytes` into the next frame and queues it, without waiting for the one being sent.
+    pub fn send(&mut self, bytes: &[u8]) -> Result<(), SendError> {
+        if bytes.len() > N {
+            return Err(SendError::TooLong);
+        }
+        self.send_with(|buf| {
+            buf[..bytes.len()].copy_from_slice(bytes);
+            bytes.len()
+        })
+    }
+
+    /// Builds the next frame in place with `fill`, which returns its length, and queues it.
+    ///
+    /// `fill` is not called while a frame is queued already. Empty frames are not sent.
+    pub fn send_with<F: FnOnce(&mut [u8; N]) -> usize>(&mut self, fill: F) -> Result<(), SendError> {
+        self.update();
+        if self.queued {
+            return Err(SendError::Busy);
+        }
+        if let Some(frame) = self.next.as_mut() {
+            frame.len = fill(frame.buf).min(N);
+            self.queued = frame.len > 0;
+        }
+        self.update();
+        Ok(())
+    }
+
+    /// True when a frame can be queued.
+    pub fn is_ready(&mut self) -> bool {
+        self.update();
+        !self.queued
+    }
+
+    /// True while frames are queued or being handed to the USART.
+    pub fn is_sending(&mut self) -> bool {
+        self.update();
+        self.sending || self.queued
+    }
+
+    /// Starts the queued frame once the last one has been sent. Call from the DMA stream interrupt handler.
+    pub fn on_interrupt(&mut self) {
+        self.update();
+    }
+
+    fn update(&mut self) {
+        if self.sending && self.channel.take_complete() {
+            self.sending = false;
+        }
+        if !self.sending && self.queued {
+            if let Some(frame) = self.next.take() {
+                self.next = Some(self.channel.start(frame));
+            }
+            self.queued = false;
+            self.sending = true;
+        }
+    }
+
+    // Release channel and the frame not held by it
+    pub fn release(mut self) -> (CH, TxFrame<N>) {
+        let frame = self.next.take().unwrap();
+        (self.channel, frame)
+    }
+}
+
+/// Circular DMA receiver handing whole idle-terminated chunks to a parser.
+pub struct DmaRx<CH, const N: usize> {
+    channel: CH,
+    ring: RxRing<N>,
+    read: usize,
+    chunk: &'static mut [u8; N],
+    len: usize,
+}
+
+impl<CH: RxChannel, const N: usize> DmaRx<CH, N> {
+    /// Receives on `channel` into `ring`, collecting chunks in `chunk`.
+    pub fn new(channel: CH, ring: RxRing<N>, chunk: &'static mut [u8; N]) -> Self {
+        Self { channel, ring, read: 0, chunk, len: 0 }
+    }
+
+    /// Hands the bytes received up to an idle line to `parse`.
+    /// Call from both the USART and the DMA stream interrupt handlers.
+    ///
+    /// A message longer than the buffer arrives in pieces, the last at the idle line.
+    pub fn on_interrupt<F: FnMut(&[u8])>(&mut self, mut parse: F) {
+        let idle = self.channel.take_idle();
+        let write = self.channel.write_index();
+        while self.read != write {
+            self.chunk[self.len] = self.ring.read(self.read);
+            self.len += 1;
+            self.read = (self.read + 1) % N;
+            if self.len == N {
+                parse(&self.chunk[..]);
+                self.len = 0;
+            }
+        }
+        if idle && self.len > 0 {
+            parse(&self.chunk[..self.len]);
+            self.len = 0;
+        }
+    }
+
+    // Release channel, ring and chunk buffer
+    pub fn release(self) -> (CH, RxRing<N>, &'static mut [u8; N]) {
+        (self.channel, self.ring, self.chunk)
+    }
+}
+
+/// USART2 transmit stream, DMA1 stream 6 channel 4.
+pub type Usart2TxTransfer<const N: usize> = Transfer<Stream6<DMA1>, 4, Tx<USART2>, MemoryToPeripheral, TxFrame<N>>;
+
+/// USART2 receive stream, DMA1 stream 5 channel 4.
+pub type Usart2RxTransfer<const N: usize> = Transfer<Stream5<DMA1>, 4, Rx<USART2>, PeripheralToMemory, RxRing<N>>;
+
+/// DMA transmitter on USART2, with `N`-byte frames.
+pub type Usart2DmaTx<const N: usize> = DmaTx<Usart2TxTransfer<N>, N>;
+
+/// DMA receiver on USART2, with `N`-byte buffers.
+pub type Usart2DmaRx<const N: usize> = DmaRx<Usart2RxTransfer<N>, N>;
+
+/// Buffers for a DMA serial port: two `TX`-byte frames, an `RX`-byte receive ring
+/// and an `RX`-byte chunk buffer, at most 65535 bytes each.
+pub struct DmaBuffers<const TX: usize, const RX: usize> {
+    tx: [[u8; TX]; 2],
+    ring: [AtomicU8; RX],
+    chunk: [u8; RX],
+}
+
+impl<const TX: usize, const RX: usize> DmaBuffers<TX, RX> {
+    // Constructor
+    pub const fn new() -> Self {
+        Self { tx: [[0; TX]; 2], ring: [const { AtomicU8::new(0) }; RX], chunk: [0; RX] }
+    }
+
+    /// Sets USART2 up with `config` for DMA, e.g. the board's `vcp`, and starts receiving.
+    ///
+    /// Call the receiver's `on_interrupt` from the USART2 and DMA1_STREAM5 handlers, the
+    /// transmitter's from DMA1_STREAM6, and unmask all three in the NVIC.
+    pub fn usart2(
+        &'static mut self,
+        serial: Serial<USART2>,
+        config: Config,
+        clocks: &Clocks,
+        tx_stream: Stream6<DMA1>,
+        rx_stream: Stream5<DMA1>,
+    ) -> Result<(Usart2DmaTx<TX>, Usart2DmaRx<RX>), InvalidConfig> {
+        let DmaBuffers { tx: [tx_a, tx_b], ring, chunk } = self;
+        let ring = RxRing::new(ring);
+
+        // The DMA requests are enabled with the USART, so set it up again.
+        let (usart, pins) = serial.release();
+        let serial = Serial::new(usart, pins, config.dma(SerialDmaConfig::TxRx), clocks)?;
+        let (tx, mut rx) = serial.split();
+        rx.listen_idle();
+
+        let tx_config = DmaConfig::default().memory_increment(true).transfer_complete_interrupt(true);
+        let rx_config = tx_config.half_transfer_interrupt(true);
+        let tx_transfer = Transfer::init_memory_to_peripheral(tx_stream, tx, TxFrame::new(tx_a), None, tx_config);
+        let mut rx_transfer = Transfer::init_peripheral_to_memory(rx_stream, rx, ring, None, rx_config);
+        // SAFETY: the stream is not enabled yet, and `next_transfer`, the one HAL call
+        // that expects the stream to stop at the end of the buffer, is never used on it.
+        #[allow(unsafe_code)]
+        unsafe {
+            rx_transfer.stream().set_circular_mode(true);
+        }
+        rx_transfer.start(|_| {});
+
+        Ok((DmaTx::new(tx_transfer, TxFrame::new(tx_b)), DmaRx::new(rx_transfer, ring, chunk)))
+    }
+}
+
+impl<const TX: usize, const RX: usize> Default for DmaBuffers<TX, RX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock::{RxChannelMock, TxChannelMock};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    fn frame<const N: usize>() -> TxFrame<N> {
+        TxFrame::new(Box::leak(Box::new([0; N])))
+    }
+
+    fn buffer<const N: usize>() -> &'static mut [u8; N] {
+        Box::leak(Box::new([0; N]))
+    }
+
+    fn ring<const N: usize>() -> &'static [AtomicU8; N] {
+        Box::leak(Box::new([const { AtomicU8::new(0) }; N]))
+    }
+
+    fn sent<const N: usize>(tx: &DmaTx<TxChannelMock<N>, N>) -> Vec<&[u8]> {
+        tx.channel.sent.iter().map(|frame| frame.as_slice()).collect()
+    }
+
+    #[test]
+    fn queues_a_frame_while_sending() {
+        let mut tx = DmaTx::new(TxChannelMock::new(frame::<16>()), frame());
+        assert_eq!(tx.send(b"first"), Ok(()));
+        assert!(tx.channel.is_busy());
+        assert_eq!(tx.send(b"second"), Ok(()));
+        assert_eq!(tx.send(b"third"), Err(SendError::Busy));
+        assert!(!tx.is_ready());
+
+        // The transfer complete interrupt starts the queued frame.
+        tx.channel.finish();
+        tx.on_interrupt();
+        assert!(tx.channel.is_busy());
+        assert!(tx.is_ready());
+        tx.channel.finish();
+        tx.on_interrupt();
+        assert!(!tx.is_sending());
+        assert_eq!(sent(&tx), [&b"first"[..], b"second"]);
+    }
+
+    #[test]
+    fn builds_frames_in_place() {
+        let mut tx = DmaTx::new(TxChannelMock::new(frame::<4>()), frame());
+        assert_eq!(tx.send(b"hello"), Err(SendError::TooLong));
+        assert_eq!(tx.send(b""), Ok(()));
+        assert!(!tx.is_sending());
+
+        let fill = |buf: &mut [u8; 4]| {
+            buf[..3].copy_from_slice(&[0xAA, 1, 2]);
+            3
+        };
+        assert_eq!(tx.send_with(fill), Ok(()));
+        tx.channel.finish();
+        // The other buffer is filled next; lengths past it are clamped.
+        assert_eq!(tx.send_with(|_| 10), Ok(()));
+        tx.channel.finish();
+        assert_eq!(sent(&tx), [&[0xAA, 1, 2][..], &[0; 4]]);
+    }
+
+    #[test]
+    fn hands_idle_terminated_chunks_to_parser() {
+        let ring = ring::<32>();
+        let mut rx = DmaRx::new(RxChannelMock::new(ring), RxRing::new(ring), buffer());
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+
+        rx.channel.receive(b"$GPS,1,2");
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+        assert!(chunks.is_empty(), "no chunk before the line is idle");
+
+        rx.channel.receive(b"*7F\r\n");
+        rx.channel.idle();
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+        rx.channel.receive(b"$IMU,3");
+        rx.channel.idle();
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+        // Idle again with nothing received: nothing to parse.
+        rx.channel.idle();
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+
+        assert_eq!(chunks, [&b"$GPS,1,2*7F\r\n"[..], b"$IMU,3"]);
+    }
+
+    #[test]
+    fn reads_chunks_across_the_end_of_the_ring() {
+        let ring = ring::<8>();
+        let mut rx = DmaRx::new(RxChannelMock::new(ring), RxRing::new(ring), buffer());
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+
+        rx.channel.receive(b"abcde");
+        rx.channel.idle();
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+        // The stream carries on at the start of the ring.
+        rx.channel.receive(b"fghij");
+        rx.channel.idle();
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+
+        assert_eq!(chunks, [&b"abcde"[..], b"fghij"]);
+    }
+
+    #[test]
+    fn splits_chunks_longer_than_buffer() {
+        let ring = ring::<8>();
+        let mut rx = DmaRx::new(RxChannelMock::new(ring), RxRing::new(ring), buffer());
+        let mut chunks: Vec<Vec<u8>> = Vec::new();
+
+        // Half and full transfer interrupts drain the ring before the stream laps it.
+        rx.channel.receive(b"0123");
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+        rx.channel.receive(b"4567");
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+        rx.channel.receive(b"89AB");
+        rx.channel.idle();
+        rx.on_interrupt(|chunk| chunks.push(chunk.to_vec()));
+
+        assert_eq!(chunks, [&b"01234567"[..], b"89AB"]);
+    }
+}
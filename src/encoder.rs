@@ -0,0 +1,194 @@
+// Generic rotary encoder interface with AS5600, timer quadrature and mock backends.
+
+// Imports
+use core::convert::Infallible;
+use embedded_hal::i2c::I2c;
+use stm32f4xx_hal::hal_02::Qei as _;
+use stm32f4xx_hal::qei::{self, Qei};
+
+use crate::angle::{Degrees, COUNTS_PER_TURN};
+use crate::as5600::Error;
+use crate::As5600;
+
+// Hardware counter type of a QEI timer, u16 or u32.
+type QeiCount<TIM> = <Qei<TIM> as stm32f4xx_hal::hal_02::Qei>::Count;
+
+/// An encoder reporting an absolute position within one revolution.
+pub trait RotaryEncoder {
+    type Error;
+
+    /// Number of counts in one revolution.
+    fn counts_per_revolution(&self) -> u32;
+
+    /// Position within the revolution, `0..counts_per_revolution()`.
+    fn position(&mut self) -> Result<u32, Self::Error>;
+
+    /// Velocity in counts per second, if the backend measures it.
+    fn velocity(&mut self) -> Result<Option<f32>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Position within the revolution in degrees (0.0 - 360.0).
+    fn degrees(&mut self) -> Result<Degrees, Self::Error> {
+        let position = self.position()?;
+        Ok(Degrees(position as f32 * 360.0 / self.counts_per_revolution() as f32))
+    }
+}
+
+// AS5600 backend, on the unscaled RAW ANGLE with the software zero and direction.
+impl<I2C, DIR, E> RotaryEncoder for As5600<I2C, DIR>
+where
+    I2C: I2c<Error = E>,
+{
+    type Error = Error<E>;
+
+    fn counts_per_revolution(&self) -> u32 {
+        COUNTS_PER_TURN as u32
+    }
+
+    fn position(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.read_position()?.counts() as u32)
+    }
+}
+
+/// Timer in quadrature encoder mode, e.g. TIM3 or TIM4 on the F401.
+///
+/// The timer counts every edge of both channels, so `counts_per_revolution` is
+/// four times the encoder's line count. The hardware counter wraps at its width,
+/// so `position` has to be called at least once per half counter range.
+pub struct QuadratureEncoder<TIM: qei::Instance> {
+    qei: Qei<TIM>,
+    counts_per_revolution: u32,
+    last: u32,
+    count: i64,
+}
+
+impl<TIM> QuadratureEncoder<TIM>
+where
+    TIM: qei::Instance,
+    QeiCount<TIM>: Into<u32>,
+{
+    // Constructor, panics if `counts_per_revolution` is 0
+    pub fn new(qei: Qei<TIM>, counts_per_revolution: u32) -> Self {
+        assert!(counts_per_revolution > 0, "an encoder needs at least one count per turn");
+        let last = qei.count().into();
+        Self {
+            qei,
+            counts_per_revolution,
+            last,
+            count: 0,
+        }
+    }
+
+    /// Signed count accumulated since construction or the last `reset`.
+    pub fn count(&mut self) -> i64 {
+        let now: u32 = self.qei.count().into();
+        let delta = now.wrapping_sub(self.last);
+        // Sign-extend the step from the counter width (16 or 32 bits).
+        self.count += match core::mem::size_of::<QeiCount<TIM>>() {
+            2 => delta as i16 as i64,
+            _ => delta as i32 as i64,
+        };
+        self.last = now;
+        self.count
+    }
+
+    /// Makes the current shaft position zero.
+    pub fn reset(&mut self) {
+        self.last = self.qei.count().into();
+        self.count = 0;
+    }
+
+    // Release peripheral
+    pub fn release(self) -> Qei<TIM> {
+        self.qei
+    }
+}
+
+impl<TIM> RotaryEncoder for QuadratureEncoder<TIM>
+where
+    TIM: qei::Instance,
+    QeiCount<TIM>: Into<u32>,
+{
+    type Error = Infallible;
+
+    fn counts_per_revolution(&self) -> u32 {
+        self.counts_per_revolution
+    }
+
+    fn position(&mut self) -> Result<u32, Self::Error> {
+        let count = self.count();
+        Ok(count.rem_euclid(self.counts_per_revolution as i64) as u32)
+    }
+}
+
+/// Encoder with a settable position, for host tests of controller code.
+#[derive(Clone, Copy, Debug)]
+pub struct MockEncoder {
+    pub counts_per_revolution: u32,
+    pub position: u32,
+    pub velocity: Option<f32>,
+}
+
+impl MockEncoder {
+    // Constructor, panics if `counts_per_revolution` is 0
+    pub fn new(counts_per_revolution: u32) -> Self {
+        assert!(counts_per_revolution > 0, "an encoder needs at least one count per turn");
+        Self {
+            counts_per_revolution,
+            position: 0,
+            velocity: None,
+        }
+    }
+
+    /// Moves the shaft by `steps` counts, wrapping within one revolution.
+    pub fn step(&mut self, steps: i32) {
+        let cpr = self.counts_per_revolution as i64;
+        self.position = (self.position as i64 + steps as i64).rem_euclid(cpr) as u32;
+    }
+}
+
+impl RotaryEncoder for MockEncoder {
+    type Error = Infallible;
+
+    fn counts_per_revolution(&self) -> u32 {
+        self.counts_per_revolution
+    }
+
+    fn position(&mut self) -> Result<u32, Self::Error> {
+        Ok(self.position)
+    }
+
+    fn velocity(&mut self) -> Result<Option<f32>, Self::Error> {
+        Ok(self.velocity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Controller-style code written once against the trait.
+    fn quarter_turns<ENC: RotaryEncoder>(encoder: &mut ENC) -> Result<u32, ENC::Error> {
+        Ok(encoder.position()? * 4 / encoder.counts_per_revolution())
+    }
+
+    #[test]
+    fn mock_wraps_and_reports_degrees() {
+        let mut encoder = MockEncoder::new(2000);
+        encoder.step(-500);
+        assert_eq!(encoder.position(), Ok(1500));
+        assert_eq!(encoder.degrees(), Ok(Degrees(270.0)));
+        assert_eq!(quarter_turns(&mut encoder), Ok(3));
+        assert_eq!(encoder.velocity(), Ok(None));
+
+        encoder.velocity = Some(12.5);
+        assert_eq!(encoder.velocity(), Ok(Some(12.5)));
+    }
+
+    #[test]
+    #[should_panic(expected = "an encoder needs at least one count per turn")]
+    fn rejects_zero_counts_per_revolution() {
+        let _ = MockEncoder::new(0);
+    }
+}
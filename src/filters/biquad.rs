@@ -0,0 +1,158 @@
+// Second-order IIR section (biquad).
+//
+// Coefficients follow the RBJ audio EQ cookbook and are normalised so a0 = 1.
+// The section runs in direct form I, which keeps the input and output history
+// separately; unlike the transposed forms, its state cannot overflow when the
+// output does not, which is what makes it usable in fixed point. With `Q16`
+// coefficients have 16 fraction bits, so keep the cutoff above roughly fs / 200
+// or the poles round onto the unit circle.
+
+// Imports
+use core::f32::consts::{FRAC_1_SQRT_2, TAU};
+
+use super::Sample;
+
+/// Biquad coefficients, designed in f32.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl BiquadCoefficients {
+    /// Quality factor of a Butterworth section: flat pass band, no overshoot peak.
+    pub const BUTTERWORTH_Q: f32 = FRAC_1_SQRT_2;
+
+    /// Low-pass with the -3 dB point at `cutoff_hz` for `q = BUTTERWORTH_Q`.
+    pub fn low_pass(cutoff_hz: f32, sample_rate_hz: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(cutoff_hz, sample_rate_hz, q);
+        let b0 = (1.0 - cos) / 2.0;
+        Self::normalised([b0, 1.0 - cos, b0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    /// High-pass with the -3 dB point at `cutoff_hz` for `q = BUTTERWORTH_Q`.
+    pub fn high_pass(cutoff_hz: f32, sample_rate_hz: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(cutoff_hz, sample_rate_hz, q);
+        let b0 = (1.0 + cos) / 2.0;
+        Self::normalised([b0, -(1.0 + cos), b0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    /// Notch removing `center_hz`, e.g. mains hum. Higher `q` gives a narrower notch.
+    pub fn notch(center_hz: f32, sample_rate_hz: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(center_hz, sample_rate_hz, q);
+        Self::normalised([1.0, -2.0 * cos, 1.0], [1.0 + alpha, -2.0 * cos, 1.0 - alpha])
+    }
+
+    // cos(w0) and sin(w0) / 2Q for the centre frequency w0.
+    fn prewarp(frequency_hz: f32, sample_rate_hz: f32, q: f32) -> (f32, f32) {
+        let w0 = TAU * frequency_hz / sample_rate_hz;
+        (libm::cosf(w0), libm::sinf(w0) / (2.0 * q))
+    }
+
+    fn normalised(b: [f32; 3], a: [f32; 3]) -> Self {
+        Self { b0: b[0] / a[0], b1: b[1] / a[0], b2: b[2] / a[0], a1: a[1] / a[0], a2: a[2] / a[0] }
+    }
+}
+
+/// Biquad filter section.
+#[derive(Clone, Copy, Debug)]
+pub struct Biquad<T> {
+    b: [T; 3],
+    a: [T; 2],
+    x: [T; 2],
+    y: [T; 2],
+}
+
+impl<T: Sample> Biquad<T> {
+    // Constructor
+    pub fn new(coefficients: BiquadCoefficients) -> Self {
+        let BiquadCoefficients { b0, b1, b2, a1, a2 } = coefficients;
+        Self {
+            b: [b0, b1, b2].map(T::from_f32),
+            a: [a1, a2].map(T::from_f32),
+            x: [T::default(); 2],
+            y: [T::default(); 2],
+        }
+    }
+
+    /// Adds a sample and returns the filtered value.
+    pub fn update(&mut self, sample: T) -> T {
+        let [b0, b1, b2] = self.b;
+        let [a1, a2] = self.a;
+        let [x1, x2] = self.x;
+        let [y1, y2] = self.y;
+        let y = b0 * sample + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+        self.x = [sample, x1];
+        self.y = [y, y1];
+        y
+    }
+
+    /// Clears the history, as if the input had been zero forever.
+    pub fn reset(&mut self) {
+        self.x = [T::default(); 2];
+        self.y = [T::default(); 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::Q16;
+
+    const FS: f32 = 1000.0;
+    const Q: f32 = BiquadCoefficients::BUTTERWORTH_Q;
+
+    // Output amplitude for a unit sine at `frequency_hz`, from the RMS over the
+    // second half, which holds whole periods for the frequencies tested.
+    fn gain(coefficients: BiquadCoefficients, frequency_hz: f32) -> f32 {
+        let mut filter = Biquad::<f32>::new(coefficients);
+        let mut sum_of_squares = 0.0f32;
+        for n in 0..4000 {
+            let y = filter.update(libm::sinf(TAU * frequency_hz * n as f32 / FS));
+            if n >= 2000 {
+                sum_of_squares += y * y;
+            }
+        }
+        libm::sqrtf(sum_of_squares / 2000.0 * 2.0)
+    }
+
+    #[test]
+    fn low_pass_response() {
+        let low_pass = BiquadCoefficients::low_pass(50.0, FS, Q);
+        assert!((gain(low_pass, 5.0) - 1.0).abs() < 0.01);
+        assert!((gain(low_pass, 50.0) - FRAC_1_SQRT_2).abs() < 0.01);
+        assert!(gain(low_pass, 400.0) < 0.02);
+    }
+
+    #[test]
+    fn high_pass_response() {
+        let high_pass = BiquadCoefficients::high_pass(50.0, FS, Q);
+        assert!(gain(high_pass, 2.0) < 0.01);
+        assert!((gain(high_pass, 50.0) - FRAC_1_SQRT_2).abs() < 0.01);
+        assert!((gain(high_pass, 400.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn notch_removes_center_frequency() {
+        let notch = BiquadCoefficients::notch(50.0, FS, 5.0);
+        assert!(gain(notch, 50.0) < 0.01);
+        assert!((gain(notch, 5.0) - 1.0).abs() < 0.01);
+        assert!((gain(notch, 300.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn fixed_point_settles_on_dc() {
+        let mut filter = Biquad::<Q16>::new(BiquadCoefficients::low_pass(20.0, FS, Q));
+        let mut y = Q16::ZERO;
+        for _ in 0..500 {
+            y = filter.update(Q16::from_int(2000));
+        }
+        // Rounding the coefficients shifts the DC gain slightly.
+        assert!((y.to_f32() - 2000.0).abs() < 10.0, "{}", y.to_f32());
+        filter.reset();
+        assert_eq!(filter.update(Q16::ZERO), Q16::ZERO);
+    }
+}
@@ -0,0 +1,82 @@
+// Exponential moving average, a single-pole IIR low-pass.
+//
+// y += alpha * (x - y). The weight comes from the cutoff frequency and sample
+// rate, alpha = 1 - exp(-2π fc / fs), which matches the -3 dB point of the
+// analog RC filter for cutoffs well below the sample rate.
+
+// Imports
+use core::f32::consts::TAU;
+
+use super::Sample;
+
+/// Single-pole low-pass filter.
+#[derive(Clone, Copy, Debug)]
+pub struct Ema<T> {
+    alpha: T,
+    value: Option<T>,
+}
+
+impl<T: Sample> Ema<T> {
+    /// Creates a filter with the -3 dB point at `cutoff_hz`, sampled at `sample_rate_hz`.
+    pub fn from_cutoff(cutoff_hz: f32, sample_rate_hz: f32) -> Self {
+        Self::with_alpha(1.0 - libm::expf(-TAU * cutoff_hz / sample_rate_hz))
+    }
+
+    /// Creates a filter with weight `alpha` for new samples, 0.0 (frozen) to 1.0 (no filtering).
+    pub fn with_alpha(alpha: f32) -> Self {
+        Self { alpha: T::from_f32(alpha.clamp(0.0, 1.0)), value: None }
+    }
+
+    /// Adds a sample and returns the filtered value. The first sample passes straight through.
+    pub fn update(&mut self, sample: T) -> T {
+        let value = match self.value {
+            Some(value) => value + self.alpha * (sample - value),
+            None => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    /// The filtered value, or `None` before the first sample.
+    pub fn value(&self) -> Option<T> {
+        self.value
+    }
+
+    /// Forgets the filtered value, so the next sample passes straight through.
+    pub fn reset(&mut self) {
+        self.value = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::Q16;
+
+    #[test]
+    fn step_reaches_63_percent_after_one_time_constant() {
+        // tau = 1 / (2π fc) = 1 s, so 1000 samples at 1 kHz.
+        let mut ema = Ema::<f32>::from_cutoff(1.0 / TAU, 1000.0);
+        ema.update(0.0);
+        let mut y = 0.0;
+        for _ in 0..1000 {
+            y = ema.update(1.0);
+        }
+        assert!((y - 0.632).abs() < 1e-3, "{}", y);
+    }
+
+    #[test]
+    fn fixed_point_tracks_float() {
+        let mut float = Ema::<f32>::with_alpha(0.09);
+        let mut fixed = Ema::<Q16>::with_alpha(0.09);
+        assert_eq!(fixed.value(), None);
+        for i in 0..200 {
+            let x = if i % 50 < 25 { 1000.0 } else { -250.0 };
+            let y = float.update(x);
+            let y_fixed = fixed.update(Q16::from_f32(x));
+            assert!((y_fixed.to_f32() - y).abs() < 0.05, "{} vs {}", y_fixed.to_f32(), y);
+        }
+        fixed.reset();
+        assert_eq!(fixed.update(Q16::from_int(7)), Q16::from_int(7));
+    }
+}
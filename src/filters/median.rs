@@ -0,0 +1,75 @@
+// Median of the last N samples, for rejecting spikes.
+//
+// A single bad reading, e.g. an I2C glitch or ADC noise from a switching motor,
+// moves a mean but not a median. The window is sorted on a stack copy each
+// update; insertion sort is quick for the small odd N this is meant for (3 to 9).
+
+/// Median of the last `N` samples. Use an odd `N`.
+#[derive(Clone, Copy, Debug)]
+pub struct MedianFilter<T, const N: usize> {
+    window: [T; N],
+    next: usize,
+    len: usize,
+}
+
+impl<T: Copy + PartialOrd + Default, const N: usize> MedianFilter<T, N> {
+    // Constructor
+    pub fn new() -> Self {
+        const { assert!(N > 0, "the window must hold at least one sample") };
+        Self { window: [T::default(); N], next: 0, len: 0 }
+    }
+
+    /// Adds a sample, dropping the oldest once the window is full, and returns the median.
+    ///
+    /// With an even number of samples, the upper of the two middle samples is returned.
+    pub fn update(&mut self, sample: T) -> T {
+        self.window[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+
+        let mut sorted = self.window;
+        let sorted = &mut sorted[..self.len];
+        for i in 1..sorted.len() {
+            let mut j = i;
+            while j > 0 && sorted[j - 1] > sorted[j] {
+                sorted.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+        sorted[sorted.len() / 2]
+    }
+
+    /// Empties the window.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<T: Copy + PartialOrd + Default, const N: usize> Default for MedianFilter<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_single_spikes() {
+        let mut median = MedianFilter::<u16, 3>::new();
+        let input = [100, 101, 4095, 102, 103, 0, 104];
+        let output = input.map(|x| median.update(x));
+        assert_eq!(output, [100, 101, 101, 102, 103, 102, 103]);
+    }
+
+    #[test]
+    fn partial_window_uses_samples_so_far() {
+        let mut median = MedianFilter::<f32, 5>::new();
+        assert_eq!(median.update(3.0), 3.0);
+        assert_eq!(median.update(1.0), 3.0);
+        assert_eq!(median.update(2.0), 2.0);
+        median.reset();
+        assert_eq!(median.update(-1.0), -1.0);
+    }
+}
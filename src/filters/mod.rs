@@ -0,0 +1,165 @@
+// Signal filters for sensor readings.
+//
+// Every filter keeps its state in fixed-size arrays, so they are no_std and
+// allocation-free, and can live in a static or an RTIC resource. The IIR filters
+// are designed in f32 from a cutoff and sample rate, then run on any `Sample`:
+// f32 on parts with an FPU, or `Q16` fixed point where floats are too slow or
+// not allowed, e.g. in a tight interrupt handler.
+
+// Imports
+use core::ops::{Add, Mul, Neg, Sub};
+
+pub mod biquad;
+pub mod ema;
+pub mod median;
+pub mod moving_average;
+
+// Re-exports
+pub use biquad::{Biquad, BiquadCoefficients};
+pub use ema::Ema;
+pub use median::MedianFilter;
+pub use moving_average::MovingAverage;
+
+/// Number type a filter runs on.
+pub trait Sample:
+    Copy + Default + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    /// Wider type for running sums, so a long window cannot overflow.
+    type Sum: Copy + Default + Add<Output = Self::Sum> + Sub<Output = Self::Sum>;
+
+    /// Converts a filter coefficient.
+    fn from_f32(value: f32) -> Self;
+    /// Widens the sample for summing.
+    fn to_sum(self) -> Self::Sum;
+    /// Mean of `count` samples summing to `sum`.
+    fn mean(sum: Self::Sum, count: usize) -> Self;
+}
+
+impl Sample for f32 {
+    type Sum = f32;
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn to_sum(self) -> f32 {
+        self
+    }
+
+    fn mean(sum: f32, count: usize) -> Self {
+        sum / count as f32
+    }
+}
+
+/// Signed fixed-point number, Q15.16: 16 integer and 16 fraction bits.
+///
+/// Range is about ±32768 with a step of 1/65536. Products round to nearest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Q16(i32);
+
+impl Q16 {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1 << 16);
+
+    /// Creates a value from its raw bits, `value * 65536`.
+    pub const fn from_bits(bits: i32) -> Self {
+        Self(bits)
+    }
+
+    /// The raw bits, `value * 65536`.
+    pub const fn bits(self) -> i32 {
+        self.0
+    }
+
+    /// Converts an integer, e.g. an ADC sample. Must lie within ±32767.
+    pub const fn from_int(value: i32) -> Self {
+        Self(value << 16)
+    }
+
+    /// The value rounded to the nearest integer.
+    pub const fn to_int(self) -> i32 {
+        (self.0 + (1 << 15)) >> 16
+    }
+
+    /// The nearest fixed-point value, saturating outside the range.
+    pub fn from_f32(value: f32) -> Self {
+        let bits = value * 65536.0;
+        // Float to int casts saturate.
+        Self(if bits < 0.0 { bits - 0.5 } else { bits + 0.5 } as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / 65536.0
+    }
+}
+
+impl Add for Q16 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Q16 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl Mul for Q16 {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        let product = self.0 as i64 * rhs.0 as i64;
+        Self(((product + (1 << 15)) >> 16) as i32)
+    }
+}
+
+impl Neg for Q16 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl defmt::Format for Q16 {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=f32}", self.to_f32())
+    }
+}
+
+impl Sample for Q16 {
+    type Sum = i64;
+
+    fn from_f32(value: f32) -> Self {
+        Q16::from_f32(value)
+    }
+
+    fn to_sum(self) -> i64 {
+        self.0 as i64
+    }
+
+    fn mean(sum: i64, count: usize) -> Self {
+        let count = count as i64;
+        let half = if sum < 0 { -count / 2 } else { count / 2 };
+        Self(((sum + half) / count) as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn q16_arithmetic_rounds_to_nearest() {
+        assert_eq!(Q16::from_int(3) * Q16::from_f32(0.5), Q16::from_f32(1.5));
+        assert_eq!(Q16::from_f32(-1.25).to_f32(), -1.25);
+        assert_eq!(Q16::from_f32(2.4).to_int(), 2);
+        assert_eq!(Q16::from_f32(2.5).to_int(), 3);
+        assert_eq!(Q16::from_f32(-2.6).to_int(), -3);
+        // Half a step is rounded up, not truncated away.
+        assert_eq!(Q16::from_bits(1) * Q16::from_f32(0.5), Q16::from_bits(1));
+        assert_eq!(Q16::from_f32(1e9), Q16::from_bits(i32::MAX));
+        assert_eq!(<Q16 as Sample>::mean(-5, 2), Q16::from_bits(-3));
+    }
+}
@@ -0,0 +1,109 @@
+// Moving average over a fixed window.
+//
+// Keeps the last N samples in a ring and a running sum, so each update is O(1).
+// An f32 running sum picks up rounding error as samples come and go, so the sum
+// is recomputed from the window each time the ring wraps. Until the window has
+// filled, the average is over the samples so far.
+
+// Imports
+use super::Sample;
+
+/// Mean of the last `N` samples.
+#[derive(Clone, Copy, Debug)]
+pub struct MovingAverage<T: Sample, const N: usize> {
+    window: [T; N],
+    next: usize,
+    len: usize,
+    sum: T::Sum,
+}
+
+impl<T: Sample, const N: usize> MovingAverage<T, N> {
+    // Constructor
+    pub fn new() -> Self {
+        const { assert!(N > 0, "the window must hold at least one sample") };
+        Self { window: [T::default(); N], next: 0, len: 0, sum: T::Sum::default() }
+    }
+
+    /// Adds a sample, dropping the oldest once the window is full, and returns the mean.
+    pub fn update(&mut self, sample: T) -> T {
+        if self.len == N {
+            self.sum = self.sum - self.window[self.next].to_sum();
+        } else {
+            self.len += 1;
+        }
+        self.window[self.next] = sample;
+        self.sum = self.sum + sample.to_sum();
+
+        self.next += 1;
+        if self.next == N {
+            self.next = 0;
+            self.sum = self.window.iter().fold(T::Sum::default(), |sum, x| sum + x.to_sum());
+        }
+        self.value()
+    }
+
+    /// Mean of the samples in the window, zero before the first.
+    pub fn value(&self) -> T {
+        if self.len == 0 { T::default() } else { T::mean(self.sum, self.len) }
+    }
+
+    /// True once `N` samples have been added.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Empties the window.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<T: Sample, const N: usize> Default for MovingAverage<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::Q16;
+
+    #[test]
+    fn averages_over_the_window() {
+        let mut average = MovingAverage::<f32, 4>::new();
+        assert_eq!(average.value(), 0.0);
+        assert_eq!(average.update(2.0), 2.0);
+        assert_eq!(average.update(4.0), 3.0);
+        average.update(6.0);
+        assert!(!average.is_full());
+        assert_eq!(average.update(8.0), 5.0);
+        // 2.0 drops out.
+        assert_eq!(average.update(10.0), 7.0);
+        assert!(average.is_full());
+        average.reset();
+        assert_eq!(average.update(1.0), 1.0);
+    }
+
+    #[test]
+    fn fixed_point_sum_does_not_overflow() {
+        let mut average = MovingAverage::<Q16, 64>::new();
+        let mut mean = Q16::ZERO;
+        for _ in 0..1000 {
+            mean = average.update(Q16::from_int(30_000));
+        }
+        assert_eq!(mean, Q16::from_int(30_000));
+    }
+
+    #[test]
+    fn float_sum_does_not_drift() {
+        let mut average = MovingAverage::<f32, 8>::new();
+        for i in 0..100_000 {
+            average.update(if i % 2 == 0 { 1e6 } else { 0.1 });
+        }
+        for _ in 0..8 {
+            average.update(0.1);
+        }
+        assert!((average.value() - 0.1).abs() < 1e-6, "{}", average.value());
+    }
+}
@@ -0,0 +1,264 @@
+// H-bridge DC motor driver on two PWM inputs, e.g. DRV8833, DRV8871 or TB6612
+// with IN1/IN2 both on PWM.
+//
+// IN1 high and IN2 low drives forward, the reverse drives backward. Both low
+// lets the motor coast, both high shorts it and brakes. The decay mode picks
+// which of the two fills the off-time of each PWM period:
+//
+//   forward, coast decay: IN1 = duty, IN2 = 0
+//   forward, brake decay: IN1 = 1,    IN2 = 1 - duty
+//
+// Brake (slow) decay gives a speed closer to linear in duty; coast (fast) decay
+// lets the motor spin down freely. Backward swaps IN1 and IN2.
+
+// Imports
+use embedded_hal::delay::DelayNs;
+use embedded_hal::pwm::SetDutyCycle;
+
+/// What the bridge does in the off-time of each PWM period, and at zero output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, defmt::Format)]
+pub enum Decay {
+    /// Both inputs low: the motor freewheels.
+    #[default]
+    Coast,
+    /// Both inputs high: the motor windings are shorted and brake.
+    Brake,
+}
+
+/// DC motor on an H-bridge, driven by two PWM channels.
+pub struct HBridge<IN1, IN2, D> {
+    in1: IN1,
+    in2: IN2,
+    delay: D,
+    decay: Decay,
+    min_duty: f32,
+    deadband: f32,
+    dead_time_us: u32,
+    forward: Option<bool>,
+    output: f32,
+}
+
+impl<IN1, IN2, D, E> HBridge<IN1, IN2, D>
+where
+    IN1: SetDutyCycle<Error = E>,
+    IN2: SetDutyCycle<Error = E>,
+    D: DelayNs,
+{
+    /// Creates the driver with the motor coasting. The PWM channels must be enabled.
+    ///
+    /// `delay` times the dead time when the direction changes.
+    pub fn new(in1: IN1, in2: IN2, delay: D) -> Result<Self, E> {
+        let mut bridge = Self {
+            in1,
+            in2,
+            delay,
+            decay: Decay::Coast,
+            min_duty: 0.0,
+            deadband: 0.0,
+            dead_time_us: 0,
+            forward: None,
+            output: 0.0,
+        };
+        bridge.coast()?;
+        Ok(bridge)
+    }
+
+    /// Selects the decay mode, `Coast` by default.
+    pub fn with_decay(mut self, decay: Decay) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    /// Smallest duty (0.0 - 1.0) that turns the motor, so small outputs are not lost to friction.
+    ///
+    /// Outputs outside the deadband are scaled onto `min_duty..=1.0`.
+    pub fn with_min_duty(mut self, min_duty: f32) -> Self {
+        self.min_duty = min_duty.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Outputs with magnitude up to `deadband` (0.0 - 1.0) leave the motor idle in the decay mode.
+    pub fn with_deadband(mut self, deadband: f32) -> Self {
+        self.deadband = deadband.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Coasts the motor for `dead_time_us` before driving it the other way.
+    pub fn with_dead_time_us(mut self, dead_time_us: u32) -> Self {
+        self.dead_time_us = dead_time_us;
+        self
+    }
+
+    /// Drives the motor with a signed output, -1.0 (full backward) to 1.0 (full forward).
+    pub fn set_output(&mut self, output: f32) -> Result<(), E> {
+        // NaN compares false everywhere, so it stops the motor.
+        let output = if output.is_nan() { 0.0 } else { output.clamp(-1.0, 1.0) };
+        self.output = output;
+
+        let magnitude = output.abs();
+        if magnitude <= self.deadband {
+            return self.idle();
+        }
+        let duty = self.min_duty + (magnitude - self.deadband) / (1.0 - self.deadband) * (1.0 - self.min_duty);
+
+        let forward = output > 0.0;
+        if self.forward == Some(!forward) && self.dead_time_us > 0 {
+            self.set_inputs(0.0, 0.0)?;
+            self.delay.delay_us(self.dead_time_us);
+        }
+        self.forward = Some(forward);
+
+        let (on, off) = match self.decay {
+            Decay::Coast => (duty, 0.0),
+            Decay::Brake => (1.0, 1.0 - duty),
+        };
+        if forward { self.set_inputs(on, off) } else { self.set_inputs(off, on) }
+    }
+
+    /// Lets the motor freewheel.
+    pub fn coast(&mut self) -> Result<(), E> {
+        self.output = 0.0;
+        self.set_inputs(0.0, 0.0)
+    }
+
+    /// Shorts the motor windings, stopping it quickly.
+    pub fn brake(&mut self) -> Result<(), E> {
+        self.output = 0.0;
+        self.set_inputs(1.0, 1.0)
+    }
+
+    /// Safe stop: coasts the motor and forgets the direction, so no drive is left on
+    /// and the next output starts from rest.
+    ///
+    /// Coasting takes no current from the supply; call `brake` to stop faster.
+    pub fn stop(&mut self) -> Result<(), E> {
+        self.forward = None;
+        self.coast()
+    }
+
+    /// Last output set, 0.0 after `coast`, `brake` or `stop`.
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    /// The decay mode in use.
+    pub fn decay(&self) -> Decay {
+        self.decay
+    }
+
+    // Stops the motor in the decay mode.
+    fn idle(&mut self) -> Result<(), E> {
+        match self.decay {
+            Decay::Coast => self.set_inputs(0.0, 0.0),
+            Decay::Brake => self.set_inputs(1.0, 1.0),
+        }
+    }
+
+    // Sets both inputs as fractions of their maximum duty.
+    fn set_inputs(&mut self, in1: f32, in2: f32) -> Result<(), E> {
+        let in1 = Self::duty(in1, self.in1.max_duty_cycle());
+        let in2 = Self::duty(in2, self.in2.max_duty_cycle());
+        // Set the lower input first, so a change of direction never passes through both high.
+        if in1 < in2 {
+            self.in1.set_duty_cycle(in1)?;
+            self.in2.set_duty_cycle(in2)
+        } else {
+            self.in2.set_duty_cycle(in2)?;
+            self.in1.set_duty_cycle(in1)
+        }
+    }
+
+    fn duty(fraction: f32, max: u16) -> u16 {
+        (fraction * max as f32 + 0.5) as u16
+    }
+
+    // Release peripherals, with the motor coasting
+    pub fn release(mut self) -> Result<(IN1, IN2, D), E> {
+        self.stop()?;
+        Ok((self.in1, self.in2, self.delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{DelayMock, PwmMock};
+
+    type Bridge = HBridge<PwmMock, PwmMock, DelayMock>;
+
+    fn bridge() -> Bridge {
+        HBridge::new(PwmMock::new(1000), PwmMock::new(1000), DelayMock::default()).unwrap()
+    }
+
+    fn duties(bridge: &Bridge) -> (u16, u16) {
+        (bridge.in1.duty, bridge.in2.duty)
+    }
+
+    #[test]
+    fn signed_output_in_both_decay_modes() {
+        let mut coast = bridge();
+        coast.set_output(0.25).unwrap();
+        assert_eq!(duties(&coast), (250, 0));
+        coast.set_output(-0.75).unwrap();
+        assert_eq!(duties(&coast), (0, 750));
+        coast.set_output(2.0).unwrap();
+        assert_eq!(duties(&coast), (1000, 0));
+        assert_eq!(coast.output(), 1.0);
+
+        let mut brake = bridge().with_decay(Decay::Brake);
+        brake.set_output(0.25).unwrap();
+        assert_eq!(duties(&brake), (1000, 750));
+        brake.set_output(-0.75).unwrap();
+        assert_eq!(duties(&brake), (250, 1000));
+        brake.set_output(0.0).unwrap();
+        assert_eq!(duties(&brake), (1000, 1000));
+    }
+
+    #[test]
+    fn deadband_and_min_duty() {
+        let mut motor = bridge().with_deadband(0.1).with_min_duty(0.2);
+        motor.set_output(0.5).unwrap();
+        motor.set_output(0.05).unwrap();
+        assert_eq!(duties(&motor), (0, 0));
+        // Just past the deadband starts at the minimum duty, full output is still full duty.
+        motor.set_output(0.1001).unwrap();
+        assert_eq!(duties(&motor), (200, 0));
+        motor.set_output(0.55).unwrap();
+        assert_eq!(duties(&motor), (600, 0));
+        motor.set_output(-1.0).unwrap();
+        assert_eq!(duties(&motor), (0, 1000));
+        motor.set_output(f32::NAN).unwrap();
+        assert_eq!(duties(&motor), (0, 0));
+    }
+
+    #[test]
+    fn dead_time_only_on_direction_change() {
+        let mut motor = bridge().with_dead_time_us(500);
+        motor.set_output(0.5).unwrap();
+        motor.set_output(0.8).unwrap();
+        assert_eq!(motor.delay.elapsed_ns, 0);
+        motor.set_output(-0.5).unwrap();
+        assert_eq!(motor.delay.elapsed_ns, 500_000);
+        motor.set_output(-0.1).unwrap();
+        assert_eq!(motor.delay.elapsed_ns, 500_000);
+
+        // A stop forgets the direction, so starting again needs no dead time.
+        motor.stop().unwrap();
+        assert_eq!(duties(&motor), (0, 0));
+        motor.set_output(0.5).unwrap();
+        assert_eq!(motor.delay.elapsed_ns, 500_000);
+    }
+
+    #[test]
+    fn coast_brake_and_release() {
+        let mut motor = bridge().with_decay(Decay::Brake);
+        motor.set_output(0.5).unwrap();
+        motor.coast().unwrap();
+        assert_eq!(duties(&motor), (0, 0));
+        motor.brake().unwrap();
+        assert_eq!(duties(&motor), (1000, 1000));
+        assert_eq!(motor.output(), 0.0);
+        let (in1, in2, _) = motor.release().unwrap();
+        assert_eq!((in1.duty, in2.duty), (0, 0));
+    }
+}
@@ -0,0 +1,424 @@
+// Bus, pin, PWM, UART, DMA and delay mocks for host tests of drivers.
+//
+// A test lists the I2C transactions it expects a driver to make, with the bytes
+// to answer reads with. The mock panics on the first transaction that differs
+// from the script, so a failing test points at the offending call. Any step can
+// fail instead, to check how a driver handles NACKs and bus errors.
+
+// Imports
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicU8, Ordering};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::{self, InputPin, OutputPin, StatefulOutputPin};
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress};
+use embedded_hal::pwm::{self, SetDutyCycle};
+use embedded_hal_nb::serial;
+use heapless::{Deque, Vec};
+
+use crate::buffered_serial::{TxInterrupt, UartPort};
+use crate::dma_serial::{RxChannel, TxChannel, TxFrame};
+
+/// One expected I2C transaction: a write, a read, or a write followed by a read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transaction<'a> {
+    address: u8,
+    write: Option<&'a [u8]>,
+    read: Option<&'a [u8]>,
+    error: Option<ErrorKind>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Expects `bytes` to be written to `address`.
+    pub const fn write(address: u8, bytes: &'a [u8]) -> Self {
+        Self { address, write: Some(bytes), read: None, error: None }
+    }
+
+    /// Expects a read from `address` of `response.len()` bytes, answered with `response`.
+    pub const fn read(address: u8, response: &'a [u8]) -> Self {
+        Self { address, write: None, read: Some(response), error: None }
+    }
+
+    /// Expects `bytes` written to `address` followed by a repeated-start read answered with `response`.
+    pub const fn write_read(address: u8, bytes: &'a [u8], response: &'a [u8]) -> Self {
+        Self { address, write: Some(bytes), read: Some(response), error: None }
+    }
+
+    /// Fails the transaction with `error` after checking it. The read buffer is left untouched.
+    pub const fn with_error(mut self, error: ErrorKind) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Fails the transaction with an address NACK, as if no device answered.
+    pub const fn nack(self) -> Self {
+        self.with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+    }
+}
+
+/// I2C bus that plays back a script of expected transactions.
+///
+/// Call `done` at the end of the test to check that nothing in the script was skipped.
+#[derive(Debug)]
+pub struct I2cMock<'a> {
+    script: &'a [Transaction<'a>],
+    next: usize,
+}
+
+impl<'a> I2cMock<'a> {
+    // Constructor
+    pub fn new(script: &'a [Transaction<'a>]) -> Self {
+        Self { script, next: 0 }
+    }
+
+    /// Number of scripted transactions not made yet.
+    pub fn remaining(&self) -> usize {
+        self.script.len() - self.next
+    }
+
+    /// Panics unless every scripted transaction has been made.
+    pub fn done(&self) {
+        assert_eq!(
+            self.remaining(),
+            0,
+            "I2C mock: {} of {} scripted transactions not made",
+            self.remaining(),
+            self.script.len()
+        );
+    }
+
+    // Check one transaction against the script and play back its answer.
+    fn play(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+        let index = self.next;
+        let Some(expected) = self.script.get(index) else {
+            panic!("I2C mock: unexpected transaction {} to {:#04x}, script has ended", index, address);
+        };
+        self.next += 1;
+
+        let (write, read) = match operations {
+            [Operation::Write(bytes)] => (Some(&**bytes), None),
+            [Operation::Read(buf)] => (None, Some(&mut **buf)),
+            [Operation::Write(bytes), Operation::Read(buf)] => (Some(&**bytes), Some(&mut **buf)),
+            _ => panic!("I2C mock: transaction {} is not a write, read or write_read", index),
+        };
+
+        assert_eq!(address, expected.address, "I2C mock: address of transaction {}", index);
+        assert_eq!(write, expected.write, "I2C mock: bytes written in transaction {}", index);
+        assert_eq!(
+            read.as_ref().map(|buf| buf.len()),
+            expected.read.map(|response| response.len()),
+            "I2C mock: bytes read in transaction {}",
+            index
+        );
+
+        if let Some(error) = expected.error {
+            return Err(error);
+        }
+        if let (Some(buf), Some(response)) = (read, expected.read) {
+            buf.copy_from_slice(response);
+        }
+        Ok(())
+    }
+}
+
+impl ErrorType for I2cMock<'_> {
+    type Error = ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for I2cMock<'_> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.play(address, operations)
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c<SevenBitAddress> for I2cMock<'_> {
+    async fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.play(address, operations)
+    }
+}
+
+/// GPIO pin whose level the test sets or inspects through `high`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PinMock {
+    pub high: bool,
+}
+
+impl digital::ErrorType for PinMock {
+    type Error = Infallible;
+}
+
+impl OutputPin for PinMock {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.high = false;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.high = true;
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for PinMock {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.high)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.high)
+    }
+}
+
+impl InputPin for PinMock {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.high)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.high)
+    }
+}
+
+/// PWM channel whose duty the test inspects through `duty`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PwmMock {
+    pub duty: u16,
+    pub max_duty: u16,
+}
+
+impl PwmMock {
+    // Constructor, starting at zero duty
+    pub fn new(max_duty: u16) -> Self {
+        Self { duty: 0, max_duty }
+    }
+}
+
+impl pwm::ErrorType for PwmMock {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for PwmMock {
+    fn max_duty_cycle(&self) -> u16 {
+        self.max_duty
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        assert!(duty <= self.max_duty, "PWM mock: duty {} above maximum {}", duty, self.max_duty);
+        self.duty = duty;
+        Ok(())
+    }
+}
+
+/// USART whose received bytes and errors the test queues, and whose sent bytes it inspects in `sent`.
+///
+/// A written byte waits in the data register until `shift_out` sends it, like the real
+/// transmitter; the enabled interrupts are in `rx_listening` and `tx_interrupt`.
+#[derive(Debug, Default)]
+pub struct UartMock {
+    pub incoming: Deque<Result<u8, serial::ErrorKind>, 64>,
+    pub sent: Vec<u8, 256>,
+    pub tdr: Option<u8>,
+    pub rx_listening: bool,
+    pub tx_interrupt: TxInterrupt,
+}
+
+impl UartMock {
+    /// Queues a byte as received.
+    pub fn receive(&mut self, byte: u8) {
+        self.incoming.push_back(Ok(byte)).expect("UART mock: incoming queue full");
+    }
+
+    /// Queues a receive error.
+    pub fn receive_error(&mut self, kind: serial::ErrorKind) {
+        self.incoming.push_back(Err(kind)).expect("UART mock: incoming queue full");
+    }
+
+    /// Sends the byte in the data register, if any.
+    pub fn shift_out(&mut self) {
+        if let Some(byte) = self.tdr.take() {
+            self.sent.push(byte).expect("UART mock: sent buffer full");
+        }
+    }
+}
+
+impl UartPort for UartMock {
+    fn read(&mut self) -> nb::Result<u8, serial::ErrorKind> {
+        match self.incoming.pop_front() {
+            Some(Ok(byte)) => Ok(byte),
+            Some(Err(kind)) => Err(nb::Error::Other(kind)),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Infallible> {
+        if self.tdr.is_some() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.tdr = Some(byte);
+        Ok(())
+    }
+
+    fn is_tx_complete(&self) -> bool {
+        self.tdr.is_none()
+    }
+
+    fn listen_rx(&mut self, enabled: bool) {
+        self.rx_listening = enabled;
+    }
+
+    fn listen_tx(&mut self, interrupt: TxInterrupt) {
+        self.tx_interrupt = interrupt;
+    }
+}
+
+/// DMA transmit stream that finishes a frame when the test calls `finish`, keeping its bytes in `sent`.
+pub struct TxChannelMock<const N: usize> {
+    pub sent: Vec<Vec<u8, N>, 8>,
+    frame: TxFrame<N>,
+    busy: bool,
+    complete: bool,
+}
+
+impl<const N: usize> TxChannelMock<N> {
+    // Constructor, holding `frame` as the HAL transfer holds its first buffer
+    pub fn new(frame: TxFrame<N>) -> Self {
+        Self { sent: Vec::new(), frame, busy: false, complete: false }
+    }
+
+    /// True while a frame is being sent.
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Finishes sending the current frame.
+    pub fn finish(&mut self) {
+        assert!(self.busy, "DMA TX mock: no frame being sent");
+        let bytes = Vec::from_slice(self.frame.as_slice()).unwrap();
+        self.sent.push(bytes).expect("DMA TX mock: sent list full");
+        self.busy = false;
+        self.complete = true;
+    }
+}
+
+impl<const N: usize> TxChannel<N> for TxChannelMock<N> {
+    fn start(&mut self, frame: TxFrame<N>) -> TxFrame<N> {
+        assert!(!self.busy, "DMA TX mock: frame started while one is being sent");
+        self.busy = true;
+        self.complete = false;
+        core::mem::replace(&mut self.frame, frame)
+    }
+
+    fn take_complete(&mut self) -> bool {
+        core::mem::take(&mut self.complete)
+    }
+}
+
+/// Circular DMA receive stream that the test feeds with `receive` and `idle`.
+pub struct RxChannelMock<const N: usize> {
+    ring: &'static [AtomicU8; N],
+    write: usize,
+    idle: bool,
+}
+
+impl<const N: usize> RxChannelMock<N> {
+    // Constructor, receiving into `ring`
+    pub fn new(ring: &'static [AtomicU8; N]) -> Self {
+        Self { ring, write: 0, idle: false }
+    }
+
+    /// Receives `bytes` into the ring, carrying on at its start like the stream does.
+    pub fn receive(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.ring[self.write].store(byte, Ordering::Relaxed);
+            self.write = (self.write + 1) % N;
+        }
+    }
+
+    /// The line goes idle.
+    pub fn idle(&mut self) {
+        self.idle = true;
+    }
+}
+
+impl<const N: usize> RxChannel for RxChannelMock<N> {
+    fn take_idle(&mut self) -> bool {
+        core::mem::take(&mut self.idle)
+    }
+
+    fn write_index(&self) -> usize {
+        self.write
+    }
+}
+
+/// Delay that returns at once and adds up the time asked for in `elapsed_ns`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DelayMock {
+    pub elapsed_ns: u64,
+}
+
+impl DelayNs for DelayMock {
+    fn delay_ns(&mut self, ns: u32) {
+        self.elapsed_ns += ns as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_back_script() {
+        let script = [
+            Transaction::write(0x36, &[0x01, 0x02]),
+            Transaction::read(0x36, &[0xAB]),
+            Transaction::write_read(0x36, &[0x0C], &[0x12, 0x34]),
+        ];
+        let mut i2c = I2cMock::new(&script);
+
+        assert_eq!(i2c.write(0x36, &[0x01, 0x02]), Ok(()));
+        let mut one = [0u8; 1];
+        assert_eq!(i2c.read(0x36, &mut one), Ok(()));
+        assert_eq!(one, [0xAB]);
+        let mut two = [0u8; 2];
+        assert_eq!(i2c.write_read(0x36, &[0x0C], &mut two), Ok(()));
+        assert_eq!(two, [0x12, 0x34]);
+        i2c.done();
+    }
+
+    #[test]
+    fn injects_errors() {
+        let script = [
+            Transaction::write(0x36, &[0x0C]).nack(),
+            Transaction::write_read(0x36, &[0x0C], &[0xFF, 0xFF]).with_error(ErrorKind::Bus),
+        ];
+        let mut i2c = I2cMock::new(&script);
+
+        assert_eq!(
+            i2c.write(0x36, &[0x0C]),
+            Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+        );
+        let mut buf = [0u8; 2];
+        assert_eq!(i2c.write_read(0x36, &[0x0C], &mut buf), Err(ErrorKind::Bus));
+        assert_eq!(buf, [0, 0]);
+        i2c.done();
+    }
+
+    #[test]
+    #[should_panic(expected = "bytes written in transaction 0")]
+    fn panics_on_wrong_bytes() {
+        let script = [Transaction::write(0x36, &[0x0C])];
+        let _ = I2cMock::new(&script).write(0x36, &[0x0E]);
+    }
+
+    #[test]
+    #[should_panic(expected = "script has ended")]
+    fn panics_on_extra_transaction() {
+        let _ = I2cMock::new(&[]).write(0x36, &[0x0C]);
+    }
+
+    #[test]
+    #[should_panic(expected = "1 of 1 scripted transactions not made")]
+    fn done_panics_on_missing_transaction() {
+        I2cMock::new(&[Transaction::read(0x36, &[0x00])]).done();
+    }
+}
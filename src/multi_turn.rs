@@ -0,0 +1,235 @@
+// Multi-turn tracking on top of a single-turn encoder angle.
+//
+// Between two samples the shaft is assumed to have taken the shortest way round.
+// A step larger than `max_step` could have wrapped either way, so it is flagged
+// instead of being counted.
+
+// Imports
+use crate::angle::{wrap_delta_in, Degrees, COUNTS_PER_TURN};
+use crate::encoder::RotaryEncoder;
+
+/// A sample moved too far from the last one to tell which way the shaft wrapped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AmbiguousStep {
+    /// Shortest-path step of the rejected sample, in counts.
+    pub delta: i32,
+}
+
+/// Unwraps single-turn angle samples into a continuous signed count.
+#[derive(Clone, Copy, Debug)]
+pub struct Unwrapper {
+    last: Option<u32>,
+    count: i64,
+    counts_per_turn: u32,
+    max_step: u32,
+}
+
+impl Unwrapper {
+    /// Creates a tracker for the 12-bit AS5600 angle that rejects steps larger
+    /// than `max_step` counts. `max_step` is capped at 2047, half a turn.
+    pub fn new(max_step: u32) -> Self {
+        Self::with_counts_per_turn(COUNTS_PER_TURN as u32, max_step)
+    }
+
+    /// Creates a tracker for an encoder with `counts_per_turn` counts per revolution.
+    /// `max_step` is capped just below half a turn. Panics if `counts_per_turn` is 0.
+    pub fn with_counts_per_turn(counts_per_turn: u32, max_step: u32) -> Self {
+        assert!(counts_per_turn > 0, "an encoder needs at least one count per turn");
+        Self {
+            last: None,
+            count: 0,
+            counts_per_turn,
+            max_step: max_step.min((counts_per_turn - 1) / 2),
+        }
+    }
+
+    /// Adds an angle sample and returns the accumulated count.
+    ///
+    /// The first sample sets the count to the angle itself. A rejected sample
+    /// leaves the tracker untouched; call `resync` if the shaft really did jump.
+    pub fn update(&mut self, raw: u32) -> Result<i64, AmbiguousStep> {
+        let raw = raw % self.counts_per_turn;
+        match self.last {
+            None => self.count = raw as i64,
+            Some(last) => {
+                let delta = wrap_delta_in(last, raw, self.counts_per_turn);
+                if delta.unsigned_abs() > self.max_step {
+                    return Err(AmbiguousStep { delta });
+                }
+                self.count += delta as i64;
+            }
+        }
+        self.last = Some(raw);
+        Ok(self.count)
+    }
+
+    /// Accepts `raw` as the new reference, counting the step to it the short way round.
+    pub fn resync(&mut self, raw: u32) {
+        let raw = raw % self.counts_per_turn;
+        if let Some(last) = self.last {
+            self.count += wrap_delta_in(last, raw, self.counts_per_turn) as i64;
+        } else {
+            self.count = raw as i64;
+        }
+        self.last = Some(raw);
+    }
+
+    /// Forgets all history and sets the accumulated count to zero.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.count = 0;
+    }
+
+    /// Accumulated count since the first sample.
+    pub fn count(&self) -> i64 {
+        self.count
+    }
+
+    /// Counts per revolution.
+    pub fn counts_per_turn(&self) -> u32 {
+        self.counts_per_turn
+    }
+
+    /// Whole revolutions, rounded towards negative infinity.
+    pub fn revolutions(&self) -> i64 {
+        self.count.div_euclid(self.counts_per_turn as i64)
+    }
+
+    /// Continuous angle in degrees.
+    pub fn degrees(&self) -> Degrees {
+        let turns = self.revolutions() as f32;
+        let rem = self.count.rem_euclid(self.counts_per_turn as i64) as f32;
+        Degrees(turns * 360.0 + rem * 360.0 / self.counts_per_turn as f32)
+    }
+}
+
+/// Errors from `MultiTurn::update`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MultiTurnError<E> {
+    /// The angle read failed.
+    Sensor(E),
+    /// The sample was rejected, see `AmbiguousStep`.
+    AmbiguousStep(AmbiguousStep),
+}
+
+/// A `RotaryEncoder`, such as an `As5600`, with multi-turn unwrapping of its angle.
+pub struct MultiTurn<ENC> {
+    encoder: ENC,
+    unwrapper: Unwrapper,
+}
+
+impl<ENC> MultiTurn<ENC>
+where
+    ENC: RotaryEncoder,
+{
+    // Constructor
+    pub fn new(encoder: ENC, max_step: u32) -> Self {
+        let counts_per_turn = encoder.counts_per_revolution();
+        Self {
+            encoder,
+            unwrapper: Unwrapper::with_counts_per_turn(counts_per_turn, max_step),
+        }
+    }
+
+    /// Reads the angle and returns the accumulated count.
+    pub fn update(&mut self) -> Result<i64, MultiTurnError<ENC::Error>> {
+        let raw = self.encoder.position().map_err(MultiTurnError::Sensor)?;
+        self.unwrapper.update(raw).map_err(MultiTurnError::AmbiguousStep)
+    }
+
+    /// Reads the angle and accepts it as the new reference.
+    pub fn resync(&mut self) -> Result<i64, ENC::Error> {
+        let raw = self.encoder.position()?;
+        self.unwrapper.resync(raw);
+        Ok(self.unwrapper.count())
+    }
+
+    /// The underlying tracker.
+    pub fn tracker(&mut self) -> &mut Unwrapper {
+        &mut self.unwrapper
+    }
+
+    /// Accumulated count since the first sample.
+    pub fn count(&self) -> i64 {
+        self.unwrapper.count()
+    }
+
+    /// Whole revolutions, rounded towards negative infinity.
+    pub fn revolutions(&self) -> i64 {
+        self.unwrapper.revolutions()
+    }
+
+    /// Continuous angle in degrees.
+    pub fn degrees(&self) -> Degrees {
+        self.unwrapper.degrees()
+    }
+
+    /// The wrapped encoder.
+    pub fn encoder(&mut self) -> &mut ENC {
+        &mut self.encoder
+    }
+
+    // Release encoder
+    pub fn release(self) -> ENC {
+        self.encoder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::MockEncoder;
+
+    #[test]
+    #[should_panic(expected = "an encoder needs at least one count per turn")]
+    fn rejects_zero_counts_per_turn() {
+        let _ = Unwrapper::with_counts_per_turn(0, 10);
+    }
+
+    #[test]
+    fn counts_forward_through_wrap() {
+        let mut tracker = Unwrapper::new(512);
+        for raw in [4000, 4090, 10, 100] {
+            tracker.update(raw).unwrap();
+        }
+        assert_eq!(tracker.count(), 4096 + 100);
+        assert_eq!(tracker.revolutions(), 1);
+    }
+
+    #[test]
+    fn counts_backward_through_wrap() {
+        let mut tracker = Unwrapper::new(512);
+        for raw in [100, 10, 4090, 4000] {
+            tracker.update(raw).unwrap();
+        }
+        assert_eq!(tracker.count(), -96);
+        assert_eq!(tracker.revolutions(), -1);
+        assert!((tracker.degrees().0 + 96.0 * 360.0 / 4096.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn flags_ambiguous_step_and_keeps_state() {
+        let mut tracker = Unwrapper::new(512);
+        tracker.update(0).unwrap();
+        assert_eq!(tracker.update(1500), Err(AmbiguousStep { delta: 1500 }));
+        assert_eq!(tracker.update(200), Ok(200));
+
+        tracker.resync(2300);
+        assert_eq!(tracker.count(), 2300 - 4096);
+    }
+
+    #[test]
+    fn tracks_any_encoder_resolution() {
+        let mut encoder = MockEncoder::new(400);
+        encoder.position = 350;
+        let mut multi_turn = MultiTurn::new(encoder, 100);
+        multi_turn.update().unwrap();
+
+        for _ in 0..6 {
+            multi_turn.encoder().step(75);
+            multi_turn.update().unwrap();
+        }
+        assert_eq!(multi_turn.count(), 350 + 6 * 75);
+        assert_eq!(multi_turn.revolutions(), 2);
+    }
+}
@@ -0,0 +1,272 @@
+// PID controller.
+//
+// The D term acts on the measurement rather than the error, so set point steps
+// do not kick the output, and runs through a first-order low-pass. The I term is
+// stored as its contribution to the output, not as the raw error integral, which
+// keeps the output steady when the gains change. When the output saturates, the
+// integrator is held (clamping) or bled off towards the limit (back-calculation).
+
+/// Controller gains.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Gains {
+    /// Proportional gain.
+    pub kp: f32,
+    /// Integral gain, per second.
+    pub ki: f32,
+    /// Derivative gain, in seconds.
+    pub kd: f32,
+}
+
+/// How the integrator is kept from winding up while the output is saturated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AntiWindup {
+    /// Stop integrating while the output is saturated and the error pushes further out.
+    Clamping,
+    /// Feed the saturation excess back into the integrator with tracking gain `kt` (1/s).
+    BackCalculation { kt: f32 },
+}
+
+/// PID controller with output limits and anti-windup.
+#[derive(Clone, Copy, Debug)]
+pub struct Pid {
+    gains: Gains,
+    min: f32,
+    max: f32,
+    d_tau: f32,
+    anti_windup: AntiWindup,
+    integral: f32,
+    d_filtered: f32,
+    last_error: f32,
+    last_measurement: Option<f32>,
+}
+
+impl Pid {
+    /// Creates a controller without output limits or D filter, with clamping anti-windup.
+    pub fn new(gains: Gains) -> Self {
+        Self {
+            gains,
+            min: f32::NEG_INFINITY,
+            max: f32::INFINITY,
+            d_tau: 0.0,
+            anti_windup: AntiWindup::Clamping,
+            integral: 0.0,
+            d_filtered: 0.0,
+            last_error: 0.0,
+            last_measurement: None,
+        }
+    }
+
+    /// Clamps the output to `min..=max`. Panics if `min > max` or either is NaN.
+    pub fn with_output_limits(mut self, min: f32, max: f32) -> Self {
+        self.set_output_limits(min, max);
+        self
+    }
+
+    /// Low-pass filters the derivative with time constant `tau` in seconds. 0.0 disables filtering.
+    pub fn with_derivative_filter(mut self, tau: f32) -> Self {
+        self.d_tau = tau.max(0.0);
+        self
+    }
+
+    /// Selects the anti-windup method, `Clamping` by default.
+    pub fn with_anti_windup(mut self, anti_windup: AntiWindup) -> Self {
+        self.anti_windup = anti_windup;
+        self
+    }
+
+    /// Runs one step `dt` seconds after the previous one and returns the output.
+    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+        let error = setpoint - measurement;
+
+        // Derivative of the measurement, negated so it opposes motion.
+        if let Some(last) = self.last_measurement.filter(|_| dt > 0.0) {
+            let rate = -(measurement - last) / dt;
+            let alpha = dt / (self.d_tau + dt);
+            self.d_filtered += alpha * (rate - self.d_filtered);
+        }
+        self.last_measurement = Some(measurement);
+        self.last_error = error;
+
+        let p = self.gains.kp * error;
+        let d = self.gains.kd * self.d_filtered;
+        let integral = self.integral + self.gains.ki * error * dt;
+        let unsaturated = p + integral + d;
+        let output = unsaturated.clamp(self.min, self.max);
+
+        self.integral = match self.anti_windup {
+            AntiWindup::Clamping => {
+                let winding_up = (unsaturated > self.max && error > 0.0) || (unsaturated < self.min && error < 0.0);
+                if winding_up { self.integral } else { integral }
+            }
+            AntiWindup::BackCalculation { kt } => integral + kt * (output - unsaturated) * dt,
+        };
+
+        (p + self.integral + d).clamp(self.min, self.max)
+    }
+
+    /// Changes the gains without a jump in the output.
+    ///
+    /// The integrator absorbs the change in the P and D terms at the last error
+    /// and derivative, so the next output continues where the last one left off.
+    pub fn set_gains(&mut self, gains: Gains) {
+        self.integral += (self.gains.kp - gains.kp) * self.last_error;
+        self.integral += (self.gains.kd - gains.kd) * self.d_filtered;
+        self.gains = gains;
+    }
+
+    /// The gains in use.
+    pub fn gains(&self) -> Gains {
+        self.gains
+    }
+
+    /// Clamps the output to `min..=max`. Panics if `min > max` or either is NaN.
+    pub fn set_output_limits(&mut self, min: f32, max: f32) {
+        assert!(min <= max, "PID output limits out of order: min {} > max {}", min, max);
+        self.min = min;
+        self.max = max;
+        self.integral = self.integral.clamp(min, max);
+    }
+
+    /// Current integral term, in output units.
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Clears the integrator, the derivative filter and the previous measurement.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.d_filtered = 0.0;
+        self.last_error = 0.0;
+        self.last_measurement = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.01;
+
+    fn close(a: f32, b: f32, tolerance: f32) -> bool {
+        (a - b).abs() < tolerance
+    }
+
+    // First-order plant y' = (u - y) / tau, stepped with forward Euler.
+    struct Plant {
+        y: f32,
+        tau: f32,
+    }
+
+    impl Plant {
+        fn step(&mut self, u: f32) -> f32 {
+            self.y += (u - self.y) / self.tau * DT;
+            self.y
+        }
+    }
+
+    // Runs a unit step for `seconds` with the actuator saturating at `u_max`.
+    // Returns the final and peak plant output, and the integral term.
+    fn step_response(pid: &mut Pid, seconds: f32, u_max: f32) -> ([f32; 2], f32) {
+        let mut plant = Plant { y: 0.0, tau: 0.5 };
+        let mut peak = 0.0f32;
+        for _ in 0..(seconds / DT) as usize {
+            let u = pid.update(1.0, plant.y, DT);
+            peak = peak.max(plant.step(u.min(u_max)));
+        }
+        ([plant.y, peak], pid.integral())
+    }
+
+    #[test]
+    fn p_only_leaves_known_steady_state_error() {
+        let mut pid = Pid::new(Gains { kp: 4.0, ..Default::default() });
+        let ([y, peak], _) = step_response(&mut pid, 10.0, f32::INFINITY);
+        // y = kp / (1 + kp) for a first-order plant with unity gain.
+        assert!(close(y, 0.8, 1e-3));
+        assert!(peak <= 0.8 + 1e-3);
+    }
+
+    #[test]
+    fn pi_removes_steady_state_error() {
+        let mut pid = Pid::new(Gains { kp: 2.0, ki: 4.0, kd: 0.0 });
+        let ([y, _], integral) = step_response(&mut pid, 20.0, f32::INFINITY);
+        assert!(close(y, 1.0, 1e-3));
+        // At rest the integrator alone holds the plant at the set point.
+        assert!(close(integral, 1.0, 1e-2));
+    }
+
+    #[test]
+    fn output_is_limited() {
+        let mut pid = Pid::new(Gains { kp: 100.0, ..Default::default() }).with_output_limits(-2.0, 1.5);
+        assert_eq!(pid.update(1.0, 0.0, DT), 1.5);
+        assert_eq!(pid.update(-1.0, 0.0, DT), -2.0);
+    }
+
+    #[test]
+    fn anti_windup_limits_overshoot() {
+        let gains = Gains { kp: 1.0, ki: 10.0, kd: 0.0 };
+        let mut clamped = Pid::new(gains).with_output_limits(0.0, 1.2);
+        let mut tracking = Pid::new(gains)
+            .with_output_limits(0.0, 1.2)
+            .with_anti_windup(AntiWindup::BackCalculation { kt: 20.0 });
+        // Unaware of the actuator limit, so its integrator winds up.
+        let mut unlimited = Pid::new(gains);
+
+        let ([y_clamped, peak_clamped], integral_clamped) = step_response(&mut clamped, 10.0, 1.2);
+        let ([y_tracking, peak_tracking], _) = step_response(&mut tracking, 10.0, 1.2);
+        let ([_, peak_unlimited], _) = step_response(&mut unlimited, 10.0, 1.2);
+
+        assert!(close(y_clamped, 1.0, 1e-3) && close(y_tracking, 1.0, 1e-3));
+        assert!(integral_clamped <= 1.2);
+        assert!(peak_clamped < peak_unlimited && peak_tracking < peak_unlimited);
+        assert!(peak_clamped < 1.05 && peak_tracking < 1.05);
+    }
+
+    #[test]
+    fn derivative_acts_on_measurement_only() {
+        let mut pid = Pid::new(Gains { kd: 0.5, ..Default::default() });
+        pid.update(0.0, 0.0, DT);
+        // A set point step does not kick the output.
+        assert_eq!(pid.update(10.0, 0.0, DT), 0.0);
+        // A measurement rising at 2/s is damped by kd * 2.
+        assert!(close(pid.update(10.0, 0.02, DT), -1.0, 1e-4));
+    }
+
+    #[test]
+    fn derivative_filter_smooths_steps() {
+        let mut pid = Pid::new(Gains { kd: 1.0, ..Default::default() }).with_derivative_filter(0.09);
+        pid.update(0.0, 0.0, DT);
+        // alpha = dt / (tau + dt) = 0.1 of the raw -1/s rate.
+        assert!(close(pid.update(0.0, 0.01, DT), -0.1, 1e-5));
+    }
+
+    #[test]
+    fn gain_change_is_bumpless() {
+        let mut pid = Pid::new(Gains { kp: 2.0, ki: 1.0, kd: 0.1 });
+        let mut plant = Plant { y: 0.0, tau: 0.5 };
+        let mut u = 0.0;
+        for _ in 0..50 {
+            u = pid.update(1.0, plant.y, DT);
+            plant.step(u);
+        }
+        pid.set_gains(Gains { kp: 8.0, ki: 1.0, kd: 0.4 });
+        let next = pid.update(1.0, plant.y, DT);
+        assert!(close(next, u, 0.05), "{} -> {}", u, next);
+    }
+
+    #[test]
+    fn reset_clears_state() {
+        let mut pid = Pid::new(Gains { kp: 1.0, ki: 1.0, kd: 1.0 });
+        pid.update(1.0, 0.0, DT);
+        pid.update(1.0, 0.5, DT);
+        pid.reset();
+        assert_eq!(pid.integral(), 0.0);
+        // No stale measurement, so no derivative on the first step.
+        assert!(close(pid.update(1.0, 0.0, DT), 1.0 + DT, 1e-6));
+    }
+
+    #[test]
+    #[should_panic(expected = "PID output limits out of order: min 1 > max -1")]
+    fn rejects_swapped_output_limits() {
+        let _ = Pid::new(Gains { kp: 1.0, ki: 0.0, kd: 0.0 }).with_output_limits(1.0, -1.0);
+    }
+}
@@ -0,0 +1,198 @@
+// Shared I2C bus devices.
+//
+// Each wrapper implements `I2c` by running the whole call on the shared bus under
+// one borrow or lock, so a driver's write_read cannot be split by another driver.
+// The first two come from embedded-hal-bus; only the RTIC one is defined here.
+// Pick the wrapper by where the drivers run:
+//
+// - `RefCellDevice`: all drivers in the same context, e.g. the superloop in `main`.
+// - `CriticalSectionDevice`: drivers in `main` and in interrupt handlers.
+// - `RticDevice`: drivers in RTIC tasks, on a `#[shared]` bus resource.
+
+// Imports
+use embedded_hal::i2c::{ErrorType, I2c, Operation, SevenBitAddress};
+
+pub use embedded_hal_bus::i2c::{CriticalSectionDevice, RefCellDevice};
+
+/// Bus device over an RTIC shared resource, or anything else implementing `rtic::Mutex`.
+///
+/// Build it inside the task from the resource proxy, e.g.
+/// `As5600::new(RticDevice::new(cx.shared.i2c))`. Every call takes the resource
+/// lock, which only blocks tasks that also use the bus.
+pub struct RticDevice<M> {
+    resource: M,
+}
+
+impl<M> RticDevice<M> {
+    // Constructor
+    pub fn new(resource: M) -> Self {
+        Self { resource }
+    }
+
+    // Release resource proxy
+    pub fn release(self) -> M {
+        self.resource
+    }
+}
+
+impl<M> ErrorType for RticDevice<M>
+where
+    M: rtic::Mutex,
+    M::T: ErrorType,
+{
+    type Error = <M::T as ErrorType>::Error;
+}
+
+impl<M> I2c<SevenBitAddress> for RticDevice<M>
+where
+    M: rtic::Mutex,
+    M::T: I2c,
+{
+    fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.resource.lock(|bus| bus.read(address, read))
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.resource.lock(|bus| bus.write(address, write))
+    }
+
+    fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Self::Error> {
+        self.resource.lock(|bus| bus.write_read(address, write, read))
+    }
+
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        self.resource.lock(|bus| bus.transaction(address, operations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::As5600;
+    use core::cell::RefCell;
+    use critical_section::Mutex;
+    use embedded_hal::i2c::ErrorKind;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::{thread, vec::Vec};
+
+    // Host critical section: a global spin lock. On the board cortex-m provides it.
+    struct HostCriticalSection;
+    critical_section::set_impl!(HostCriticalSection);
+
+    static CS_LOCK: AtomicBool = AtomicBool::new(false);
+
+    #[allow(unsafe_code)]
+    unsafe impl critical_section::Impl for HostCriticalSection {
+        unsafe fn acquire() -> critical_section::RawRestoreState {
+            while CS_LOCK.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+                core::hint::spin_loop();
+            }
+            Default::default()
+        }
+
+        unsafe fn release(_: critical_section::RawRestoreState) {
+            CS_LOCK.store(false, Ordering::Release);
+        }
+    }
+
+    // Bus that logs every write and read with its address, and fails if a
+    // transaction starts while another one is still running.
+    #[derive(Default)]
+    struct LogBus {
+        log: Vec<(u8, char)>,
+        busy: AtomicBool,
+    }
+
+    impl ErrorType for LogBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for LogBus {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            assert!(!self.busy.swap(true, Ordering::SeqCst), "transactions overlapped");
+            for op in operations {
+                match op {
+                    Operation::Write(_) => self.log.push((address, 'w')),
+                    Operation::Read(buf) => {
+                        thread::yield_now();
+                        buf.fill(address);
+                        self.log.push((address, 'r'));
+                    }
+                }
+            }
+            self.busy.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    // Each write must be followed by the read of the same write_read.
+    fn assert_pairs_intact(log: &[(u8, char)]) {
+        assert_eq!(log.len() % 2, 0);
+        for pair in log.chunks(2) {
+            assert_eq!((pair[0].1, pair[1].1), ('w', 'r'));
+            assert_eq!(pair[0].0, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn refcell_devices_interleave_whole_transactions() {
+        let bus = RefCell::new(LogBus::default());
+        let mut encoder = As5600::new(RefCellDevice::new(&bus));
+        let mut other = RefCellDevice::new(&bus);
+
+        for _ in 0..4 {
+            encoder.read_agc().unwrap();
+            let mut buf = [0u8; 1];
+            other.write_read(0x48, &[0x00], &mut buf).unwrap();
+            assert_eq!(buf, [0x48]);
+        }
+
+        let log = bus.into_inner().log;
+        assert_eq!(log.len(), 16);
+        assert_pairs_intact(&log);
+    }
+
+    #[test]
+    fn critical_section_devices_serialise_threads() {
+        static BUS: Mutex<RefCell<LogBus>> = Mutex::new(RefCell::new(LogBus {
+            log: Vec::new(),
+            busy: AtomicBool::new(false),
+        }));
+
+        let workers: Vec<_> = [0x36u8, 0x48]
+            .into_iter()
+            .map(|address| {
+                thread::spawn(move || {
+                    let mut device = CriticalSectionDevice::new(&BUS);
+                    for _ in 0..200 {
+                        let mut buf = [0u8; 2];
+                        device.write_read(address, &[0x0C], &mut buf).unwrap();
+                        assert_eq!(buf, [address; 2]);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        critical_section::with(|cs| {
+            let log = &BUS.borrow_ref(cs).log;
+            assert_eq!(log.len(), 800);
+            assert_pairs_intact(log);
+        });
+    }
+
+    #[test]
+    fn rtic_device_locks_the_resource() {
+        let mut bus = LogBus::default();
+        {
+            let mut encoder = As5600::new(RticDevice::new(rtic::Exclusive(&mut bus)));
+            assert_eq!(encoder.read_agc(), Ok(0x36));
+            assert_eq!(encoder.read_magnitude(), Ok(0x0636));
+        }
+        assert_pairs_intact(&bus.log);
+    }
+}
@@ -0,0 +1,189 @@
+// TCA9548A 8-channel I2C multiplexer.
+//
+// Each downstream channel is handed out as a proxy implementing `I2c`, so drivers
+// with a fixed address, like several `As5600`, can share one bus. A proxy selects
+// its channel before each transaction, skipping the select write when the mux is
+// already on that channel.
+
+// Imports
+use core::cell::{Cell, RefCell};
+use embedded_hal::i2c::{ErrorType, I2c, Operation, SevenBitAddress};
+
+/// Number of downstream channels.
+pub const CHANNELS: u8 = 8;
+
+// Driver struct
+pub struct Tca9548a<I2C> {
+    i2c: RefCell<I2C>,
+    address: u8,
+    selected: Cell<Option<u8>>,
+}
+
+// Driver implementation
+impl<I2C, E> Tca9548a<I2C>
+where
+    I2C: I2c<Error = E>,
+{
+    /// 7-bit I2C address with A0-A2 low.
+    pub const DEFAULT_ADDR: u8 = 0x70;
+
+    // Constructor
+    pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, Self::DEFAULT_ADDR)
+    }
+
+    /// Creates a driver for a mux with address pins A0-A2 strapped to `address`.
+    pub fn with_address(i2c: I2C, address: u8) -> Self {
+        Self {
+            i2c: RefCell::new(i2c),
+            address,
+            selected: Cell::new(None),
+        }
+    }
+
+    /// Returns the proxy for downstream channel `channel` (0..=7).
+    ///
+    /// Panics if `channel` is out of range.
+    pub fn channel(&self, channel: u8) -> MuxChannel<'_, I2C> {
+        assert!(channel < CHANNELS, "TCA9548A has channels 0..=7");
+        MuxChannel { mux: self, channel }
+    }
+
+    /// Returns proxies for all eight channels.
+    pub fn split(&self) -> [MuxChannel<'_, I2C>; CHANNELS as usize] {
+        core::array::from_fn(|channel| self.channel(channel as u8))
+    }
+
+    /// Disconnects all downstream channels.
+    pub fn disable_all(&self) -> Result<(), E> {
+        self.selected.set(None);
+        self.i2c.borrow_mut().write(self.address, &[0])
+    }
+
+    /// The channel the mux is known to be on, if any.
+    pub fn selected(&self) -> Option<u8> {
+        self.selected.get()
+    }
+
+    // Release peripheral
+    pub fn release(self) -> I2C {
+        self.i2c.into_inner()
+    }
+
+    // Select `channel` unless it is already selected.
+    fn select(&self, i2c: &mut I2C, channel: u8) -> Result<(), E> {
+        if self.selected.get() != Some(channel) {
+            // Forget the cached channel first, a failed write leaves it unknown.
+            self.selected.set(None);
+            i2c.write(self.address, &[1 << channel])?;
+            self.selected.set(Some(channel));
+        }
+        Ok(())
+    }
+}
+
+/// One downstream channel of a `Tca9548a`.
+pub struct MuxChannel<'a, I2C> {
+    mux: &'a Tca9548a<I2C>,
+    channel: u8,
+}
+
+impl<I2C, E> MuxChannel<'_, I2C>
+where
+    I2C: I2c<Error = E>,
+{
+    /// Channel number of this proxy.
+    pub fn number(&self) -> u8 {
+        self.channel
+    }
+}
+
+impl<I2C: ErrorType> ErrorType for MuxChannel<'_, I2C> {
+    type Error = I2C::Error;
+}
+
+impl<I2C: I2c> I2c<SevenBitAddress> for MuxChannel<'_, I2C> {
+    fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), Self::Error> {
+        let mut i2c = self.mux.i2c.borrow_mut();
+        self.mux.select(&mut i2c, self.channel)?;
+        i2c.transaction(address, operations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{As5600, RawAngle};
+    use embedded_hal::i2c::ErrorKind;
+
+    // Records (address, first written byte) of every write and answers reads with `reply`.
+    struct Recorder {
+        writes: [(u8, u8); 16],
+        len: usize,
+        reply: [u8; 2],
+        fail_next: bool,
+    }
+
+    impl Recorder {
+        fn new() -> Self {
+            Self { writes: [(0, 0); 16], len: 0, reply: [0x01, 0x00], fail_next: false }
+        }
+
+        fn writes(&self) -> &[(u8, u8)] {
+            &self.writes[..self.len]
+        }
+    }
+
+    impl ErrorType for Recorder {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for Recorder {
+        fn transaction(&mut self, address: u8, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+            if self.fail_next {
+                self.fail_next = false;
+                return Err(ErrorKind::Bus);
+            }
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => {
+                        self.writes[self.len] = (address, bytes[0]);
+                        self.len += 1;
+                    }
+                    Operation::Read(buf) => buf.copy_from_slice(&self.reply[..buf.len()]),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn selects_channel_once_per_switch() {
+        let mux = Tca9548a::new(Recorder::new());
+        let [_, mut pitch, mut yaw, ..] = mux.split();
+        let mut pitch = As5600::new(&mut pitch);
+        let mut yaw = As5600::new(&mut yaw);
+
+        assert_eq!(pitch.read_raw_angle(), Ok(RawAngle::new(0x100)));
+        assert_eq!(pitch.read_raw_angle(), Ok(RawAngle::new(0x100)));
+        assert_eq!(yaw.read_raw_angle(), Ok(RawAngle::new(0x100)));
+        assert_eq!(mux.selected(), Some(2));
+        assert_eq!(
+            mux.release().writes(),
+            &[(0x70, 1 << 1), (0x36, 0x0C), (0x36, 0x0C), (0x70, 1 << 2), (0x36, 0x0C)]
+        );
+    }
+
+    #[test]
+    fn failed_select_is_retried() {
+        let mut recorder = Recorder::new();
+        recorder.fail_next = true;
+        let mux = Tca9548a::with_address(recorder, 0x71);
+        let mut channel = mux.channel(5);
+
+        assert_eq!(channel.write(0x36, &[0x0C]), Err(ErrorKind::Bus));
+        assert_eq!(mux.selected(), None);
+        assert_eq!(channel.write(0x36, &[0x0C]), Ok(()));
+        assert_eq!(mux.release().writes(), &[(0x71, 1 << 5), (0x36, 0x0C)]);
+    }
+}
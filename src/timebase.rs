@@ -0,0 +1,334 @@
+// Timebase from the DWT cycle counter.
+//
+// The examples used to wait with `asm::delay(n * 8_000)`, which assumes an 8 MHz
+// core; the F401 starts on the 16 MHz HSI and the PLL can take it to 84 MHz.
+// Here the counter rate comes from the frozen `Clocks`, so delays stay right
+// whatever the clock setup. The cycle counter runs at HCLK and is free, so
+// SysTick is left for RTIC or the HAL.
+//
+// CYCCNT is 32 bits and wraps every 2^32 cycles, about 51 s at 84 MHz and 24 s
+// at 180 MHz. `now` extends it to 64 bits, so it must be called at least that
+// often; polling a `Deadline` or `Periodic` in a superloop, or any delay, does so.
+
+// Imports
+use core::cell::Cell;
+use core::ops::{Add, Sub};
+use core::time::Duration;
+use cortex_m::peripheral::{DCB, DWT};
+use critical_section::Mutex;
+use embedded_hal::delay::DelayNs;
+use stm32f4xx_hal::rcc::Clocks;
+
+/// A point in time, in microseconds since the timebase started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, defmt::Format)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub const ZERO: Self = Self(0);
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros)
+    }
+
+    /// Microseconds since the timebase started.
+    pub const fn as_micros(self) -> u64 {
+        self.0
+    }
+
+    /// Milliseconds since the timebase started. Truncate to u32 for a wrapping
+    /// timestamp, e.g. for `Button::update`.
+    pub const fn as_millis(self) -> u64 {
+        self.0 / 1000
+    }
+
+    /// Time from `earlier` to `self`, zero if `earlier` is later.
+    pub fn duration_since(self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Self;
+    fn add(self, rhs: Duration) -> Self {
+        Self(self.0 + micros(rhs))
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Self;
+    fn sub(self, rhs: Duration) -> Self {
+        Self(self.0.saturating_sub(micros(rhs)))
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+    fn sub(self, rhs: Self) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+// Whole microseconds in a duration, saturating.
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros().min(u64::MAX as u128) as u64
+}
+
+/// A timeout to poll without blocking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct Deadline {
+    at: Instant,
+}
+
+impl Deadline {
+    /// A deadline `timeout` after `now`.
+    pub fn new(now: Instant, timeout: Duration) -> Self {
+        Self { at: now + timeout }
+    }
+
+    /// When the deadline expires.
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+
+    /// True once `now` has reached the deadline.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.at
+    }
+
+    /// Time left until the deadline, zero once expired.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.at.duration_since(now)
+    }
+}
+
+/// A fixed-rate tick to poll without blocking, e.g. for a control loop.
+///
+/// Ticks are scheduled from the previous tick, not from when it was polled, so
+/// the rate does not drift with the loop's run time. Ticks missed while the
+/// loop was busy are skipped, not made up in a burst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub struct Periodic {
+    next: Instant,
+    period_us: u64,
+    missed: u32,
+}
+
+impl Periodic {
+    /// Ticks every `period`, the first one `period` after `now`.
+    pub fn new(now: Instant, period: Duration) -> Self {
+        let period_us = micros(period).max(1);
+        Self { next: Instant(now.0 + period_us), period_us, missed: 0 }
+    }
+
+    /// True once per period, when `now` has reached the next tick.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if now < self.next {
+            return false;
+        }
+        let behind = (now.0 - self.next.0) / self.period_us;
+        self.missed = self.missed.saturating_add(behind as u32);
+        self.next = Instant(self.next.0 + (behind + 1) * self.period_us);
+        true
+    }
+
+    /// The tick period.
+    pub fn period(&self) -> Duration {
+        Duration::from_micros(self.period_us)
+    }
+
+    /// Ticks skipped so far because the loop polled too late.
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+
+    /// Restarts the ticks, the next one `period` after `now`.
+    pub fn reset(&mut self, now: Instant) {
+        self.next = Instant(now.0 + self.period_us);
+    }
+}
+
+// 64-bit cycle count built from CYCCNT readings.
+#[derive(Clone, Copy, Debug, Default)]
+struct CycleCount {
+    last: u32,
+    total: u64,
+}
+
+impl CycleCount {
+    // Adds the cycles since the last reading, assuming CYCCNT wrapped at most once.
+    fn advance(self, cyccnt: u32) -> Self {
+        Self { last: cyccnt, total: self.total + cyccnt.wrapping_sub(self.last) as u64 }
+    }
+}
+
+// Cycles at `hz` to whole microseconds, without overflow for any u64 count.
+fn cycles_to_micros(cycles: u64, hz: u32) -> u64 {
+    let hz = hz as u64;
+    cycles / hz * 1_000_000 + cycles % hz * 1_000_000 / hz
+}
+
+/// Monotonic clock and delays on the DWT cycle counter.
+///
+/// Methods take `&self`, so one timebase can be shared by reference, including
+/// with interrupt handlers; `&Timebase` is itself a `DelayNs` for drivers that
+/// take a delay by value.
+pub struct Timebase {
+    dcb: DCB,
+    dwt: DWT,
+    hz: u32,
+    cycles: Mutex<Cell<CycleCount>>,
+}
+
+impl Timebase {
+    /// Starts the cycle counter from zero, at the HCLK rate in `clocks`.
+    pub fn new(mut dcb: DCB, mut dwt: DWT, clocks: &Clocks) -> Self {
+        dcb.enable_trace();
+        DWT::unlock();
+        dwt.enable_cycle_counter();
+        dwt.set_cycle_count(0);
+        Self { dcb, dwt, hz: clocks.hclk().raw(), cycles: Mutex::new(Cell::new(CycleCount::default())) }
+    }
+
+    /// Counter rate, the core clock in Hz.
+    pub fn hz(&self) -> u32 {
+        self.hz
+    }
+
+    /// The current time.
+    pub fn now(&self) -> Instant {
+        let total = critical_section::with(|cs| {
+            let cycles = self.cycles.borrow(cs);
+            let count = cycles.get().advance(DWT::cycle_count());
+            cycles.set(count);
+            count.total
+        });
+        Instant(cycles_to_micros(total, self.hz))
+    }
+
+    /// A deadline `timeout` from now.
+    pub fn deadline(&self, timeout: Duration) -> Deadline {
+        Deadline::new(self.now(), timeout)
+    }
+
+    /// Ticks every `period`, starting one period from now.
+    pub fn periodic(&self, period: Duration) -> Periodic {
+        Periodic::new(self.now(), period)
+    }
+
+    /// Blocks for at least `ns` nanoseconds.
+    pub fn delay_ns(&self, ns: u32) {
+        self.delay_cycles((ns as u64 * self.hz as u64).div_ceil(1_000_000_000));
+    }
+
+    /// Blocks for at least `us` microseconds.
+    pub fn delay_us(&self, us: u32) {
+        self.delay_cycles((us as u64 * self.hz as u64).div_ceil(1_000_000));
+    }
+
+    /// Blocks for at least `ms` milliseconds.
+    pub fn delay_ms(&self, ms: u32) {
+        self.delay_cycles((ms as u64 * self.hz as u64).div_ceil(1_000));
+    }
+
+    // Busy-waits in chunks well inside the counter's wrap, keeping `now` up to date.
+    fn delay_cycles(&self, cycles: u64) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let chunk = remaining.min(1 << 30) as u32;
+            let start = DWT::cycle_count();
+            while DWT::cycle_count().wrapping_sub(start) < chunk {}
+            self.now();
+            remaining -= chunk as u64;
+        }
+    }
+
+    // Release peripherals
+    pub fn release(self) -> (DCB, DWT) {
+        (self.dcb, self.dwt)
+    }
+}
+
+impl DelayNs for Timebase {
+    fn delay_ns(&mut self, ns: u32) {
+        Timebase::delay_ns(self, ns)
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        Timebase::delay_us(self, us)
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        Timebase::delay_ms(self, ms)
+    }
+}
+
+impl DelayNs for &Timebase {
+    fn delay_ns(&mut self, ns: u32) {
+        Timebase::delay_ns(self, ns)
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        Timebase::delay_us(self, us)
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        Timebase::delay_ms(self, ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Instant {
+        Instant::from_micros(ms * 1000)
+    }
+
+    #[test]
+    fn cycle_count_extends_past_wrap() {
+        let count = CycleCount::default().advance(u32::MAX - 9);
+        let count = count.advance(20);
+        assert_eq!(count.total, u32::MAX as u64 + 21);
+        // 84 MHz for an hour, well past u64 overflow of cycles * 1_000_000.
+        assert_eq!(cycles_to_micros(84_000_000 * 3600, 84_000_000), 3_600_000_000);
+        assert_eq!(cycles_to_micros(83, 84_000_000), 0);
+        assert_eq!(cycles_to_micros(16_000_017, 16_000_000), 1_000_001);
+    }
+
+    #[test]
+    fn instant_arithmetic() {
+        let start = ms(100);
+        assert_eq!(start + Duration::from_millis(50), ms(150));
+        assert_eq!(ms(150) - start, Duration::from_millis(50));
+        assert_eq!(start - ms(150), Duration::ZERO);
+        assert_eq!(start - Duration::from_secs(1), Instant::ZERO);
+        assert_eq!(ms(1234).as_millis(), 1234);
+    }
+
+    #[test]
+    fn deadline_expires() {
+        let deadline = Deadline::new(ms(10), Duration::from_millis(5));
+        assert!(!deadline.is_expired(ms(14)));
+        assert_eq!(deadline.remaining(ms(14)), Duration::from_millis(1));
+        assert!(deadline.is_expired(ms(15)));
+        assert_eq!(deadline.remaining(ms(20)), Duration::ZERO);
+    }
+
+    #[test]
+    fn periodic_keeps_rate_and_skips_missed_ticks() {
+        let mut tick = Periodic::new(ms(0), Duration::from_millis(10));
+        assert!(!tick.poll(ms(9)));
+        // Polled late, the next tick is still on the 10 ms grid.
+        assert!(tick.poll(ms(13)));
+        assert!(!tick.poll(ms(19)));
+        assert!(tick.poll(ms(20)));
+        // Busy for 35 ms: one tick, not four.
+        assert!(tick.poll(ms(55)));
+        assert!(!tick.poll(ms(59)));
+        assert_eq!(tick.missed(), 2);
+        assert!(tick.poll(ms(60)));
+        tick.reset(ms(61));
+        assert!(!tick.poll(ms(70)));
+        assert!(tick.poll(ms(71)));
+    }
+}
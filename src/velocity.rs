@@ -0,0 +1,190 @@
+// Angular velocity estimation from encoder samples.
+//
+// The velocity is the wrap-aware angle step divided by the sample interval, run
+// through a first-order low-pass with time constant `tau`. The filter weight is
+// recomputed from dt on every sample, so fixed and variable sample rates give the
+// same response. Samples are positions within one revolution, so any
+// `RotaryEncoder` backend can feed the estimator.
+
+// Imports
+use core::f32::consts::PI;
+
+use crate::angle::{wrap_delta_in, COUNTS_PER_TURN};
+use crate::encoder::RotaryEncoder;
+
+/// Filtered angular velocity estimator.
+#[derive(Clone, Copy, Debug)]
+pub struct VelocityEstimator {
+    tau: f32,
+    counts_per_turn: u32,
+    last_position: Option<u32>,
+    last_timestamp_us: u32,
+    counts_per_second: f32,
+}
+
+impl VelocityEstimator {
+    /// Creates an estimator for the 12-bit AS5600 angle with low-pass time constant
+    /// `tau` in seconds. 0.0 disables filtering.
+    pub fn new(tau: f32) -> Self {
+        Self::with_counts_per_turn(COUNTS_PER_TURN as u32, tau)
+    }
+
+    /// Creates an estimator for an encoder with `counts_per_turn` counts per revolution.
+    /// Panics if `counts_per_turn` is 0.
+    pub fn with_counts_per_turn(counts_per_turn: u32, tau: f32) -> Self {
+        assert!(counts_per_turn > 0, "an encoder needs at least one count per turn");
+        Self {
+            tau: tau.max(0.0),
+            counts_per_turn,
+            last_position: None,
+            last_timestamp_us: 0,
+            counts_per_second: 0.0,
+        }
+    }
+
+    /// Creates an estimator matching `encoder`'s resolution.
+    pub fn for_encoder<ENC: RotaryEncoder>(encoder: &ENC, tau: f32) -> Self {
+        Self::with_counts_per_turn(encoder.counts_per_revolution(), tau)
+    }
+
+    /// Adds a position, in counts within one revolution, sampled at `timestamp_us`,
+    /// a free-running microsecond counter. Returns the velocity in degrees per second.
+    ///
+    /// The counter may wrap; intervals are taken with wrapping arithmetic.
+    pub fn update(&mut self, position: u32, timestamp_us: u32) -> f32 {
+        let dt_us = timestamp_us.wrapping_sub(self.last_timestamp_us);
+        if self.last_position.is_some() && dt_us == 0 {
+            return self.degrees_per_second();
+        }
+        self.last_timestamp_us = timestamp_us;
+        self.update_dt(position, dt_us as f32 * 1e-6)
+    }
+
+    /// Adds a position taken `dt` seconds after the previous one, for fixed-period loops.
+    /// Returns the velocity in degrees per second.
+    pub fn update_dt(&mut self, position: u32, dt: f32) -> f32 {
+        let position = position % self.counts_per_turn;
+        if let Some(last) = self.last_position.filter(|_| dt > 0.0) {
+            let sample = wrap_delta_in(last, position, self.counts_per_turn) as f32 / dt;
+            let alpha = dt / (self.tau + dt);
+            self.counts_per_second += alpha * (sample - self.counts_per_second);
+        }
+        self.last_position = Some(position);
+        self.degrees_per_second()
+    }
+
+    /// Reads the position from `encoder` and adds it with `timestamp_us`.
+    /// Returns the velocity in degrees per second.
+    pub fn read<ENC: RotaryEncoder>(&mut self, encoder: &mut ENC, timestamp_us: u32) -> Result<f32, ENC::Error> {
+        debug_assert_eq!(encoder.counts_per_revolution(), self.counts_per_turn, "encoder resolution differs");
+        let position = encoder.position()?;
+        Ok(self.update(position, timestamp_us))
+    }
+
+    /// Forgets the previous sample and the filtered velocity.
+    pub fn reset(&mut self) {
+        self.last_position = None;
+        self.counts_per_second = 0.0;
+    }
+
+    /// Filtered velocity in encoder counts per second.
+    pub fn counts_per_second(&self) -> f32 {
+        self.counts_per_second
+    }
+
+    /// Filtered velocity in degrees per second.
+    pub fn degrees_per_second(&self) -> f32 {
+        self.counts_per_second * 360.0 / self.counts_per_turn as f32
+    }
+
+    /// Filtered velocity in radians per second.
+    pub fn radians_per_second(&self) -> f32 {
+        self.counts_per_second * 2.0 * PI / self.counts_per_turn as f32
+    }
+
+    /// Filtered velocity in revolutions per minute.
+    pub fn rpm(&self) -> f32 {
+        self.counts_per_second * 60.0 / self.counts_per_turn as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::MockEncoder;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn unfiltered_velocity_across_wrap() {
+        let mut estimator = VelocityEstimator::new(0.0);
+        estimator.update_dt(4000, 0.01);
+        // 4000 -> 104 is +200 counts forward in 10 ms.
+        estimator.update_dt(104, 0.01);
+        assert!(close(estimator.counts_per_second(), 20_000.0));
+        assert!(close(estimator.rpm(), 20_000.0 * 60.0 / 4096.0));
+        assert!(close(estimator.radians_per_second(), 20_000.0 * 2.0 * PI / 4096.0));
+    }
+
+    #[test]
+    fn timestamps_give_variable_dt_and_survive_counter_wrap() {
+        let mut estimator = VelocityEstimator::new(0.0);
+        estimator.update(0, u32::MAX - 499);
+        // 1 ms later, across the u32 wrap, 4000 counts/s backwards.
+        estimator.update(4092, 500);
+        assert!(close(estimator.degrees_per_second(), -4.0 * 1000.0 * 360.0 / 4096.0));
+
+        estimator.update(4088, 2_500);
+        assert!(close(estimator.counts_per_second(), -2_000.0));
+    }
+
+    #[test]
+    fn repeated_timestamp_is_ignored() {
+        let mut estimator = VelocityEstimator::new(0.0);
+        estimator.update(0, 100);
+        estimator.update(10, 1_100);
+        estimator.update(500, 1_100);
+        assert!(close(estimator.counts_per_second(), 10_000.0));
+    }
+
+    #[test]
+    fn filter_converges_to_constant_speed() {
+        let mut estimator = VelocityEstimator::new(0.05);
+        let mut position = 0;
+        estimator.update_dt(position, 0.001);
+        for _ in 0..1000 {
+            position = (position + 41) % 4096;
+            estimator.update_dt(position, 0.001);
+        }
+        assert!(close(estimator.counts_per_second(), 41_000.0));
+    }
+
+    #[test]
+    fn filter_weight_does_not_depend_on_sample_rate() {
+        let mut fast = VelocityEstimator::new(0.1);
+        let mut slow = VelocityEstimator::new(0.1);
+        fast.update_dt(0, 0.001);
+        slow.update_dt(0, 0.01);
+        for i in 1..=10 {
+            fast.update_dt(i * 10, 0.001);
+        }
+        slow.update_dt(100, 0.01);
+        // A step to 10 000 counts/s seen over 10 ms: both sit between 0 and the step.
+        assert!(fast.counts_per_second() > 0.0 && fast.counts_per_second() < 10_000.0);
+        assert!((fast.counts_per_second() - slow.counts_per_second()).abs() < 100.0);
+    }
+
+    #[test]
+    fn reads_any_encoder_backend() {
+        // 2000-count quadrature-style encoder, 100 counts forward per 10 ms.
+        let mut encoder = MockEncoder::new(2000);
+        let mut estimator = VelocityEstimator::for_encoder(&encoder, 0.0);
+        encoder.step(-50);
+        assert_eq!(estimator.read(&mut encoder, 0), Ok(0.0));
+        encoder.step(100);
+        assert!(close(estimator.read(&mut encoder, 10_000).unwrap(), 10_000.0 * 360.0 / 2000.0));
+        assert!(close(estimator.rpm(), 10_000.0 * 60.0 / 2000.0));
+    }
+}